@@ -0,0 +1,45 @@
+//! Serves a JSON document built from a struct, and accepts one in a POST.
+//!
+//! Run with `cargo run --example json --features json` and then
+//! `curl http://127.0.0.1:8080/users/1` or
+//! `curl -H 'Content-Type: application/json' -d '{"name":"x"}' http://127.0.0.1:8080/users`.
+
+use rustful::error::ServerError;
+use rustful::rest_server::{HttpMethod, HttpRequest, HttpResponse, RestServer};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+struct User {
+    id: String,
+    name: String,
+}
+
+fn get_user(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    let user = User {
+        id: req.param("id").unwrap_or_default().to_string(),
+        name: "Ferris".to_string(),
+    };
+    return HttpResponse::json(user);
+}
+
+#[derive(Deserialize)]
+struct NewUser {
+    name: String,
+}
+
+fn create_user(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    let new_user: NewUser = req.json()?;
+    let user = User {
+        id: "2".to_string(),
+        name: new_user.name,
+    };
+    return HttpResponse::json(user);
+}
+
+fn main() {
+    let mut svr = RestServer::new("json-example", "127.0.0.1", 8080).unwrap();
+    svr.register_path("/users/:id", get_user).unwrap();
+    svr.register(HttpMethod::POST, "/users", create_user)
+        .unwrap();
+    svr.listen().unwrap();
+}
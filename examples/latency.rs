@@ -0,0 +1,57 @@
+//! Compares small-response latency with and without `TCP_NODELAY`.
+//!
+//! Run with `cargo run --release --example latency`. Without nodelay, the
+//! response head and body go out as separate small segments and Nagle's
+//! algorithm holds the second one back until the first is acknowledged.
+
+use std::{
+    io::{Error, Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rustful::rest_server::{self, RestServer};
+
+const REQUESTS: u32 = 50;
+
+fn measure(port: u16, nodelay: bool) -> Result<Duration, Error> {
+    let mut svr = RestServer::builder()
+        .name("latency")
+        .port(port)
+        .nodelay(nodelay)
+        .build()?;
+    svr.register_path("/ping", rest_server::handle_ping)?;
+    svr.set_access_logger(|_| {});
+    let handle = svr.shutdown_handle();
+
+    return thread::scope(|scope| {
+        let _ = scope.spawn(|| svr.listen());
+        thread::sleep(Duration::from_millis(100));
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+        stream.set_nodelay(true)?;
+        let mut buf = [0u8; 1024];
+        let start = Instant::now();
+        for _ in 0..REQUESTS {
+            stream.write_all(b"GET /ping HTTP/1.1\r\n\r\n")?;
+            let mut len = 0;
+            while !buf[..len].ends_with(b"pong") {
+                len += stream.read(&mut buf[len..])?;
+            }
+        }
+        let elapsed = start.elapsed();
+        drop(stream);
+        handle.shutdown();
+        return Ok(elapsed / REQUESTS);
+    });
+}
+
+fn main() {
+    for (port, nodelay) in [(8081, false), (8082, true)] {
+        match measure(port, nodelay) {
+            Ok(latency) => println!("nodelay={nodelay}: {latency:?} per request"),
+            Err(err) => println!("nodelay={nodelay}: {err}"),
+        }
+    }
+}
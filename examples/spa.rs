@@ -0,0 +1,33 @@
+//! Serves a single-page app: files under `/assets` come from disk, and every
+//! other path gets `index.html`, so the app's client-side router can handle
+//! URLs like `/users/42` on a reload.
+//!
+//! Run with `cargo run --example spa -- <dist>`, where `<dist>` holds
+//! `index.html` and an `assets` directory, and then
+//! `curl http://127.0.0.1:8080/users/42`.
+
+use std::{env, fs};
+
+use rustful::rest_server::{self, HttpResponse, RestServer};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("usage: {0} <dist>", args[0]);
+        return;
+    }
+    let dist = args[1].clone();
+
+    let mut svr = RestServer::new("spa-example", "127.0.0.1", 8080).unwrap();
+    svr.register_path("/api/ping", rest_server::handle_ping)
+        .unwrap();
+    svr.serve_dir("/assets", &format!("{dist}/assets")).unwrap();
+    let index = format!("{dist}/index.html");
+    svr.set_fallback(move |_req| {
+        let html = fs::read_to_string(&index)?;
+        let mut resp = HttpResponse::ok(html);
+        resp.set_header("Content-Type", "text/html; charset=utf-8");
+        return Ok(resp);
+    });
+    svr.listen().unwrap();
+}
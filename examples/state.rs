@@ -0,0 +1,30 @@
+//! Shares a visit counter between requests through application state.
+//!
+//! Run with `cargo run --example state` and then
+//! `curl http://127.0.0.1:8080/visits` a few times.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rustful::error::ServerError;
+use rustful::rest_server::{HttpRequest, RestServer};
+
+struct Counter {
+    visits: AtomicU64,
+}
+
+fn visits(req: HttpRequest) -> Result<String, ServerError> {
+    let counter = req
+        .state::<Counter>()
+        .ok_or_else(|| ServerError::HandlerError("counter state is not registered".to_string()))?;
+    let n = counter.visits.fetch_add(1, Ordering::Relaxed) + 1;
+    return Ok(format!("visit number {n}\n"));
+}
+
+fn main() {
+    let mut svr = RestServer::new("state-example", "127.0.0.1", 8080).unwrap();
+    svr.add_state(Counter {
+        visits: AtomicU64::new(0),
+    });
+    svr.register_path("/visits", visits).unwrap();
+    svr.listen().unwrap();
+}
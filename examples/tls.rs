@@ -0,0 +1,24 @@
+//! Serves `/ping` over HTTPS.
+//!
+//! Create a self-signed certificate with
+//! `openssl req -x509 -newkey rsa:2048 -nodes -subj /CN=localhost -keyout key.pem -out cert.pem`,
+//! run with `cargo run --example tls --features tls -- cert.pem key.pem` and
+//! then `curl -k https://127.0.0.1:8443/ping`.
+
+use std::env;
+
+use rustful::rest_server::{self, RestServer};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {0} <cert.pem> <key.pem>", args[0]);
+        return;
+    }
+
+    let mut svr = RestServer::new("tls-server", "127.0.0.1", 8443).unwrap();
+    svr.with_tls(&args[1], &args[2]).unwrap();
+    svr.register_path("/ping", rest_server::handle_ping)
+        .unwrap();
+    svr.listen().unwrap();
+}
@@ -0,0 +1,101 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use crate::date::clf_date;
+use crate::rest_server::{HttpMethod, HttpVersion};
+
+/// AccessLogEntry describes one handled request, passed to the access logger
+/// after its response has been written. Requests answered before their
+/// request line could be parsed, e.g. with `414 URI Too Long`, are logged
+/// without a method, path or version.
+pub struct AccessLogEntry<'a> {
+    pub method: Option<HttpMethod>,
+    pub version: Option<HttpVersion>,
+    pub path: Option<&'a str>,
+    /// Template of the route that matched, e.g. `/users/:id`, if any.
+    pub route: Option<&'a str>,
+    /// Address of the client that sent the request.
+    pub remote_addr: SocketAddr,
+    /// ID of the request, also sent to the client as `X-Request-Id`.
+    pub request_id: &'a str,
+    pub status: u16,
+    /// Number of response body bytes written.
+    pub bytes: usize,
+    /// Time from reading the request line to writing the response.
+    pub duration: Duration,
+}
+
+/// Receives one entry per request. Set with `RestServer::set_access_logger`.
+pub type AccessLogger = Box<dyn Fn(&AccessLogEntry) + Send + Sync>;
+
+/// The default access logger: prints a line like
+/// `GET /ping 200 4 0ms 18c3e5a0f4b2d9e100000000`, i.e. method, path,
+/// status, body bytes, duration and request ID. Parts of the request that
+/// are not known are printed as `-`.
+pub fn default_access_logger(entry: &AccessLogEntry) {
+    println!(
+        "{0} {1} {2} {3} {4}ms {5}",
+        entry.method.map_or("-", |m| m.as_str()),
+        entry.path.unwrap_or("-"),
+        entry.status,
+        entry.bytes,
+        entry.duration.as_millis(),
+        entry.request_id
+    );
+}
+
+/// An access logger printing lines in the Common Log Format used by Apache
+/// and nginx, e.g.
+/// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /ping HTTP/1.1" 200 4`.
+/// The byte count, `%b` in Apache's notation, is `-` for an empty body, and
+/// the request line is `-` if it could not be parsed.
+pub fn common_access_logger(entry: &AccessLogEntry) {
+    println!("{0}", common_log_line(entry, SystemTime::now()));
+}
+
+/// Formats an entry as a Common Log Format line, for a request whose
+/// response was written at `now`:
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use rustful::access_log::{common_log_line, AccessLogEntry};
+/// use rustful::rest_server::{HttpMethod, HttpVersion};
+///
+/// let entry = AccessLogEntry {
+///     method: Some(HttpMethod::GET),
+///     version: Some(HttpVersion::Http11),
+///     path: Some("/ping"),
+///     route: Some("/ping"),
+///     remote_addr: "127.0.0.1:50000".parse().unwrap(),
+///     request_id: "1",
+///     status: 200,
+///     bytes: 4,
+///     duration: Duration::from_secs(1),
+/// };
+/// let now = UNIX_EPOCH + Duration::from_secs(971_185_737);
+/// assert_eq!(
+///     common_log_line(&entry, now),
+///     "127.0.0.1 - - [10/Oct/2000:13:48:56 +0000] \"GET /ping HTTP/1.1\" 200 4"
+/// );
+/// ```
+pub fn common_log_line(entry: &AccessLogEntry, now: SystemTime) -> String {
+    let received = now.checked_sub(entry.duration).unwrap_or(now);
+    let bytes = match entry.bytes {
+        0 => "-".to_string(),
+        n => n.to_string(),
+    };
+    let request = match (entry.method, entry.path, entry.version) {
+        (Some(method), Some(path), Some(version)) => {
+            format!("{0} {path} {1}", method.as_str(), version.as_str())
+        }
+        _ => "-".to_string(),
+    };
+    return format!(
+        "{0} - - [{1}] \"{request}\" {2} {bytes}",
+        entry.remote_addr.ip(),
+        clf_date(received),
+        entry.status,
+    );
+}
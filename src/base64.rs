@@ -0,0 +1,99 @@
+/// Decodes standard (RFC 4648) base64 with `=` padding. Returns `None` for
+/// input that is not valid base64.
+pub(crate) fn decode(input: &str) -> Option<Vec<u8>> {
+    let bytes = input.as_bytes();
+    if bytes.len() % 4 != 0 {
+        return None;
+    }
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len() / 4 * 3);
+    for (i, quad) in bytes.chunks(4).enumerate() {
+        let last = i + 1 == bytes.len() / 4;
+        let padding = quad.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n: u32 = 0;
+        for &b in &quad[..4 - padding] {
+            n = (n << 6) | u32::from(sextet(b)?);
+        }
+        n <<= 6 * padding as u32;
+        let decoded = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        out.extend_from_slice(&decoded[..3 - padding]);
+    }
+    return Some(out);
+}
+
+/// Encodes bytes as standard (RFC 4648) base64 with `=` padding.
+#[cfg(feature = "websocket")]
+pub(crate) fn encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    return out;
+}
+
+fn sextet(b: u8) -> Option<u8> {
+    return match b {
+        b'A'..=b'Z' => Some(b - b'A'),
+        b'a'..=b'z' => Some(b - b'a' + 26),
+        b'0'..=b'9' => Some(b - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+
+    /// RFC 4648 section 10, covering no padding, `==` and `=`.
+    const VECTORS: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    #[test]
+    fn decodes_test_vectors() {
+        for (plain, encoded) in VECTORS {
+            assert_eq!(
+                decode(encoded).as_deref(),
+                Some(plain.as_bytes()),
+                "{encoded}"
+            );
+        }
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn encodes_test_vectors() {
+        for (plain, encoded) in VECTORS {
+            assert_eq!(super::encode(plain.as_bytes()), encoded, "{plain}");
+        }
+        assert_eq!(super::encode(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        for input in ["Zg", "Zg=", "Z===", "Zg==Zg==", "Zm9v!A==", "Zm 9"] {
+            assert_eq!(decode(input), None, "{input}");
+        }
+    }
+}
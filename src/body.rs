@@ -0,0 +1,161 @@
+use std::io::{BufRead, Error, ErrorKind, Read};
+
+/// Why a request body could not be read.
+pub(crate) enum BodyError {
+    /// The framing was invalid, e.g. a chunk size that is not hex.
+    Malformed(String),
+    /// The body is larger than the configured limit.
+    TooLarge,
+    /// Reading from the connection failed.
+    Io(Error),
+}
+
+impl From<Error> for BodyError {
+    fn from(err: Error) -> Self {
+        return BodyError::Io(err);
+    }
+}
+
+/// Limits on the trailer fields after a chunked body, the same ones that
+/// apply to the header lines of the message.
+#[derive(Clone, Copy)]
+pub(crate) struct TrailerLimits {
+    /// The most trailer lines accepted.
+    pub(crate) max_fields: usize,
+    /// The most bytes accepted for all trailer lines together, counting
+    /// their line breaks.
+    pub(crate) max_bytes: usize,
+}
+
+/// Reads a body sent with `Transfer-Encoding: chunked`, up to `limit` bytes
+/// of decoded data. Chunk extensions and trailer headers are discarded.
+pub(crate) fn read_chunked(
+    reader: &mut impl BufRead,
+    limit: usize,
+    trailer_limits: TrailerLimits,
+) -> Result<Vec<u8>, BodyError> {
+    let mut body: Vec<u8> = Vec::new();
+    loop {
+        let size = read_chunk_size(reader)?;
+        if size == 0 {
+            break;
+        }
+        if size > limit - body.len() {
+            return Err(BodyError::TooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        read_chunk_end(reader)?;
+    }
+
+    read_trailers(reader, trailer_limits)?;
+    return Ok(body);
+}
+
+/// Reads a chunk-size line, ignoring any chunk extensions.
+pub(crate) fn read_chunk_size(reader: &mut impl BufRead) -> Result<usize, BodyError> {
+    let line = read_crlf_line(reader)?;
+    let size_str = line.split(';').next().unwrap_or("").trim();
+    return match usize::from_str_radix(size_str, 16) {
+        Ok(size) if !size_str.starts_with('+') => Ok(size),
+        _ => Err(BodyError::Malformed(format!(
+            "invalid chunk size: {size_str:?}"
+        ))),
+    };
+}
+
+/// Reads the line break that must directly follow a chunk's data.
+pub(crate) fn read_chunk_end(reader: &mut impl BufRead) -> Result<(), BodyError> {
+    if !read_crlf_line(reader)?.is_empty() {
+        return Err(BodyError::Malformed(
+            "chunk data longer than its declared size".to_string(),
+        ));
+    }
+    return Ok(());
+}
+
+/// Skips any trailer headers up to the empty line that ends a chunked body.
+/// Fails if there are more of them than `limits` allow.
+pub(crate) fn read_trailers(
+    reader: &mut impl BufRead,
+    limits: TrailerLimits,
+) -> Result<(), BodyError> {
+    let mut fields = 0;
+    let mut bytes = 0;
+    loop {
+        let line = read_crlf_line(reader)?;
+        if line.is_empty() {
+            return Ok(());
+        }
+        fields += 1;
+        bytes += line.len() + 2;
+        if fields > limits.max_fields || bytes > limits.max_bytes {
+            return Err(BodyError::Malformed(
+                "chunked body has too many trailer fields".to_string(),
+            ));
+        }
+    }
+}
+
+/// Reads one line terminated by CRLF (or a bare LF) and returns it without
+/// the terminator. Hitting the end of input first is an error.
+fn read_crlf_line(reader: &mut impl BufRead) -> Result<String, BodyError> {
+    let mut line: Vec<u8> = Vec::new();
+    // A chunk-size or trailer line has no business being long
+    let n = reader.by_ref().take(4096).read_until(b'\n', &mut line)?;
+    if n == 0 || line.last() != Some(&b'\n') {
+        return Err(BodyError::Malformed(
+            "unterminated line in chunked body".to_string(),
+        ));
+    }
+    let _ = line.pop();
+    if line.last() == Some(&b'\r') {
+        let _ = line.pop();
+    }
+    return String::from_utf8(line)
+        .map_err(|_| BodyError::Malformed("chunk line is not valid UTF-8".to_string()));
+}
+
+impl From<BodyError> for Error {
+    fn from(err: BodyError) -> Self {
+        return match err {
+            BodyError::Malformed(msg) => Error::new(ErrorKind::InvalidData, msg),
+            BodyError::TooLarge => Error::new(ErrorKind::InvalidData, "body is too large"),
+            BodyError::Io(err) => err,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_chunked, BodyError, TrailerLimits};
+
+    const LIMITS: TrailerLimits = TrailerLimits {
+        max_fields: 2,
+        max_bytes: 32,
+    };
+
+    #[test]
+    fn reads_trailers_within_the_limits() {
+        let mut input: &[u8] = b"2\r\nhi\r\n0\r\nA: 1\r\nB: 2\r\n\r\nnext";
+        assert_eq!(read_chunked(&mut input, 100, LIMITS).ok().unwrap(), b"hi");
+        assert_eq!(input, b"next");
+    }
+
+    #[test]
+    fn rejects_too_many_trailers() {
+        let mut input: &[u8] = b"0\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n";
+        assert!(matches!(
+            read_chunked(&mut input, 100, LIMITS),
+            Err(BodyError::Malformed(_))
+        ));
+
+        let mut input: &[u8] = b"0\r\nA: 0123456789012345678901234567\r\n\r\n";
+        assert!(matches!(
+            read_chunked(&mut input, 100, LIMITS),
+            Err(BodyError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::Write;
+
+use flate2::{write::GzEncoder, Compression};
+
+use crate::rest_server::HttpResponse;
+
+/// Gzips the response body in place if the client accepts gzip, the body is
+/// at least `min_size` bytes, and its content type is worth compressing.
+/// Partial content is left alone, since its range refers to the
+/// uncompressed body.
+pub(crate) fn compress(resp: &mut HttpResponse, accept_encoding: Option<&str>, min_size: usize) {
+    if !accepts_gzip(accept_encoding.unwrap_or(""))
+        || resp.status() == 206
+        || resp.body().len() < min_size
+        || resp.header("content-encoding").is_some()
+        || resp.header("content-type").is_some_and(is_precompressed)
+    {
+        return;
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let compressed = match encoder
+        .write_all(resp.body())
+        .and_then(|()| encoder.finish())
+    {
+        Ok(bytes) => bytes,
+        // Sending the body uncompressed is always an option
+        Err(_) => return,
+    };
+    resp.set_header("Content-Encoding", "gzip");
+    resp.add_vary("Accept-Encoding");
+    resp.set_encoded_body(compressed);
+}
+
+/// Returns true if an `Accept-Encoding` value allows gzip, i.e. lists `gzip`
+/// or `*` without `q=0`.
+fn accepts_gzip(accept_encoding: &str) -> bool {
+    return accept_encoding.split(',').any(|item| {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim();
+        if !coding.eq_ignore_ascii_case("gzip") && coding != "*" {
+            return false;
+        }
+        let q = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        q > 0.0
+    });
+}
+
+/// Content types that are already compressed and would only grow.
+fn is_precompressed(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    if mime == "image/svg+xml" {
+        return false;
+    }
+    return mime.starts_with("image/")
+        || mime.starts_with("audio/")
+        || mime.starts_with("video/")
+        || mime.starts_with("font/woff")
+        || matches!(
+            mime.as_str(),
+            "application/gzip" | "application/zip" | "application/pdf"
+        );
+}
@@ -0,0 +1,120 @@
+use std::{
+    io::{Cursor, Error, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+/// Transport is a byte stream requests are read from and responses written
+/// to, such as a plain or TLS `Connection`, or a `MemoryTransport`.
+pub(crate) trait Transport: Read + Write {
+    /// Sets how long a read may block before failing with a timeout. Streams
+    /// that never block may ignore it.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error>;
+}
+
+impl<T: Transport> Transport for &mut T {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        return (**self).set_read_timeout(timeout);
+    }
+}
+
+/// MemoryTransport reads a request from a byte buffer and collects whatever
+/// is written back, for driving the server without a socket.
+pub(crate) struct MemoryTransport<'a> {
+    input: Cursor<&'a [u8]>,
+    output: Vec<u8>,
+}
+
+impl<'a> MemoryTransport<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        return MemoryTransport {
+            input: Cursor::new(input),
+            output: Vec::new(),
+        };
+    }
+
+    /// Returns everything written to the transport.
+    pub(crate) fn into_output(self) -> Vec<u8> {
+        return self.output;
+    }
+}
+
+impl Transport for MemoryTransport<'_> {
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> Result<(), Error> {
+        // Reads from memory never block
+        return Ok(());
+    }
+}
+
+impl Read for MemoryTransport<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        return self.input.read(buf);
+    }
+}
+
+impl Write for MemoryTransport<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        return self.output.write(buf);
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        return Ok(());
+    }
+}
+
+/// Connection is an accepted client connection, either plain TCP or TLS.
+pub(crate) enum Connection {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
+}
+
+impl Transport for Connection {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        return match self {
+            Connection::Plain(stream) => stream.set_read_timeout(timeout),
+            #[cfg(feature = "tls")]
+            Connection::Tls(tls) => tls.sock.set_read_timeout(timeout),
+        };
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        return match self {
+            Connection::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Connection::Tls(tls) => tls.read(buf),
+        };
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        return match self {
+            Connection::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Connection::Tls(tls) => tls.write(buf),
+        };
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        return match self {
+            Connection::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Connection::Tls(tls) => tls.flush(),
+        };
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // Tell the client the connection is closing on purpose, as far as
+        // the socket still allows
+        if let Connection::Tls(tls) = self {
+            tls.conn.send_close_notify();
+            let _ = tls.conn.complete_io(&mut tls.sock);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use crate::error::ServerError;
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    fn as_str(&self) -> &'static str {
+        return match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        };
+    }
+}
+
+/// CookieOptions holds the attributes sent with a cookie by
+/// `HttpResponse::set_cookie`. The default sets none of them.
+#[derive(Debug, Clone, Default)]
+pub struct CookieOptions {
+    pub path: Option<String>,
+    pub http_only: bool,
+    pub secure: bool,
+    /// How long the cookie lives. Zero asks the client to delete it.
+    pub max_age: Option<Duration>,
+    pub same_site: Option<SameSite>,
+}
+
+/// Formats the value of a `Set-Cookie` header. Fails with
+/// `ServerError::HandlerError` if the name or value contains characters a
+/// cookie cannot carry, such as `;`, spaces or control characters.
+pub(crate) fn format_set_cookie(
+    name: &str,
+    value: &str,
+    options: &CookieOptions,
+) -> Result<String, ServerError> {
+    if name.is_empty() || !name.bytes().all(is_token_byte) {
+        return Err(ServerError::HandlerError(format!(
+            "invalid cookie name {name:?}"
+        )));
+    }
+    if !value.bytes().all(is_cookie_value_byte) {
+        return Err(ServerError::HandlerError(format!(
+            "invalid value for cookie {name}"
+        )));
+    }
+    let mut cookie = format!("{name}={value}");
+    if let Some(path) = &options.path {
+        if !path.bytes().all(|b| b.is_ascii_graphic() && b != b';') {
+            return Err(ServerError::HandlerError(format!(
+                "invalid path for cookie {name}"
+            )));
+        }
+        cookie.push_str(&format!("; Path={path}"));
+    }
+    if let Some(max_age) = options.max_age {
+        cookie.push_str(&format!("; Max-Age={0}", max_age.as_secs()));
+    }
+    if let Some(same_site) = options.same_site {
+        cookie.push_str(&format!("; SameSite={0}", same_site.as_str()));
+    }
+    if options.secure {
+        cookie.push_str("; Secure");
+    }
+    if options.http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    return Ok(cookie);
+}
+
+/// Finds the value of the named cookie in a `Cookie` header such as
+/// `a=1; b=2`. Surrounding double quotes are removed.
+pub(crate) fn find_cookie<'h>(header: &'h str, name: &str) -> Option<&'h str> {
+    return header.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key.trim() != name {
+            return None;
+        }
+        let value = value.trim();
+        return Some(
+            value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value),
+        );
+    });
+}
+
+/// Characters allowed in a cookie name, i.e. an HTTP token.
+fn is_token_byte(b: u8) -> bool {
+    return b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b);
+}
+
+/// Characters allowed in a cookie value by RFC 6265.
+fn is_cookie_value_byte(b: u8) -> bool {
+    return b.is_ascii_graphic() && !matches!(b, b'"' | b',' | b';' | b'\\');
+}
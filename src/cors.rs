@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use crate::error::ServerError;
+use crate::middleware::{Middleware, Next};
+use crate::rest_server::{HttpMethod, HttpRequest, HttpResponse};
+
+/// Which origins a `Cors` middleware lets through.
+enum AllowedOrigins {
+    /// Any origin, answered with `Access-Control-Allow-Origin: *`.
+    Any,
+    /// Only the listed origins, each echoed back when it matches.
+    List(Vec<String>),
+}
+
+/// Cors is a middleware that answers CORS preflight requests and adds
+/// `Access-Control-Allow-Origin` to responses for allowed origins.
+///
+/// ```no_run
+/// use rustful::cors::Cors;
+/// use rustful::rest_server::{HttpMethod, RestServer};
+///
+/// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+/// svr.use_middleware(
+///     Cors::allow_origins(&["https://app.example.com"])
+///         .allow_methods(&[HttpMethod::GET, HttpMethod::POST])
+///         .allow_headers(&["Content-Type", "Authorization"]),
+/// );
+/// ```
+///
+/// Requests without an `Origin` header, or from an origin that is not
+/// allowed, pass through unchanged.
+pub struct Cors {
+    origins: AllowedOrigins,
+    methods: Vec<HttpMethod>,
+    headers: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Allows requests from any origin.
+    pub fn any() -> Self {
+        return Cors::new(AllowedOrigins::Any);
+    }
+
+    /// Allows requests only from the given origins, such as
+    /// `https://example.com`. Origins are compared case-insensitively.
+    pub fn allow_origins(origins: &[&str]) -> Self {
+        let origins = origins.iter().map(|o| o.to_string()).collect();
+        return Cors::new(AllowedOrigins::List(origins));
+    }
+
+    fn new(origins: AllowedOrigins) -> Self {
+        return Cors {
+            origins,
+            methods: vec![
+                HttpMethod::GET,
+                HttpMethod::HEAD,
+                HttpMethod::POST,
+                HttpMethod::PUT,
+                HttpMethod::PATCH,
+                HttpMethod::DELETE,
+            ],
+            headers: Vec::new(),
+            max_age: None,
+        };
+    }
+
+    /// Sets the methods allowed in preflight responses. Defaults to GET,
+    /// HEAD, POST, PUT, PATCH and DELETE.
+    pub fn allow_methods(mut self, methods: &[HttpMethod]) -> Self {
+        self.methods = methods.to_vec();
+        return self;
+    }
+
+    /// Sets the request headers allowed in preflight responses. Defaults to
+    /// none beyond the CORS-safelisted ones.
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.iter().map(|h| h.to_string()).collect();
+        return self;
+    }
+
+    /// Sets how long clients may cache a preflight response.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        return self;
+    }
+
+    /// Returns the `Access-Control-Allow-Origin` value for `origin`, or
+    /// `None` if it is not allowed.
+    fn allow_origin_value(&self, origin: &str) -> Option<String> {
+        return match &self.origins {
+            AllowedOrigins::Any => Some("*".to_string()),
+            AllowedOrigins::List(list) => list
+                .iter()
+                .any(|o| o.eq_ignore_ascii_case(origin))
+                .then(|| origin.to_string()),
+        };
+    }
+
+    fn add_origin_headers(&self, resp: &mut HttpResponse, allow_origin: &str) {
+        resp.set_header("Access-Control-Allow-Origin", allow_origin);
+        if let AllowedOrigins::List(_) = self.origins {
+            // The response differs per origin, so caches must key on it
+            resp.add_vary("Origin");
+        }
+    }
+}
+
+impl Middleware for Cors {
+    fn handle(&self, req: HttpRequest, next: Next) -> Result<HttpResponse, ServerError> {
+        let allow_origin = match req
+            .header("origin")
+            .and_then(|o| self.allow_origin_value(o))
+        {
+            Some(value) => value,
+            None => return next(req),
+        };
+
+        let preflight = req.method() == HttpMethod::OPTIONS
+            && req.header("access-control-request-method").is_some();
+        if preflight {
+            let mut resp = HttpResponse::new(204, "");
+            self.add_origin_headers(&mut resp, &allow_origin);
+            let methods: Vec<&str> = self.methods.iter().map(|m| m.as_str()).collect();
+            resp.set_header("Access-Control-Allow-Methods", &methods.join(", "));
+            if !self.headers.is_empty() {
+                resp.set_header("Access-Control-Allow-Headers", &self.headers.join(", "));
+            }
+            if let Some(max_age) = self.max_age {
+                resp.set_header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+            }
+            return Ok(resp);
+        }
+
+        // Errors get the headers too, once the server has answered them
+        let mut resp = next(req).unwrap_or_else(HttpResponse::from_error);
+        self.add_origin_headers(&mut resp, &allow_origin);
+        return Ok(resp);
+    }
+}
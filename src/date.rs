@@ -0,0 +1,130 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a time as an RFC 7231 IMF-fixdate, e.g.
+/// `Sun, 06 Nov 1994 08:49:37 GMT`. Times before the epoch are clamped to it.
+pub(crate) fn http_date(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    return format!(
+        "{0}, {day:02} {1} {year} {2:02}:{3:02}:{4:02} GMT",
+        DAYS[(days % 7) as usize],
+        MONTHS[(month - 1) as usize],
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60,
+    );
+}
+
+/// Formats a time the way the Common Log Format does, e.g.
+/// `10/Oct/2000:13:55:36 +0000`, in UTC.
+pub(crate) fn clf_date(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    return format!(
+        "{day:02}/{0}/{year}:{1:02}:{2:02}:{3:02} +0000",
+        MONTHS[(month - 1) as usize],
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60,
+    );
+}
+
+/// Converts days since 1970-01-01 into a (year, month, day) civil date.
+/// See Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    return (year, month, day);
+}
+
+/// Parses an RFC 7231 IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+/// The obsolete RFC 850 and asctime formats are not accepted.
+pub(crate) fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let (_, rest) = s.trim().split_once(", ")?;
+    let parts: Vec<&str> = rest.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts.as_slice() else {
+        return None;
+    };
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| m == month)? as u32 + 1;
+    let year: i64 = year.parse().ok()?;
+    let hms: Vec<u64> = time
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    let [h, m, sec] = hms.as_slice() else {
+        return None;
+    };
+    // Four-digit years only, so the arithmetic below cannot overflow
+    if !(1..=9999).contains(&year) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if *h > 23 || *m > 59 || *sec > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days
+        .checked_mul(86400)?
+        .checked_add(h * 3600 + m * 60 + sec)?;
+    return UNIX_EPOCH.checked_add(Duration::from_secs(secs));
+}
+
+/// Converts a (year, month, day) civil date into days since 1970-01-01, the
+/// inverse of `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    return era * 146097 + doe - 719468;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::parse_http_date;
+
+    #[test]
+    fn parses_an_imf_fixdate() {
+        let parsed = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parsed, Some(UNIX_EPOCH + Duration::from_secs(784_111_777)));
+    }
+
+    #[test]
+    fn rejects_years_out_of_range() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 300000000000 08:49:37 GMT"),
+            None
+        );
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 9223372036854775807 08:49:37 GMT"),
+            None
+        );
+        assert_eq!(parse_http_date("Sun, 06 Nov 10000 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1969 08:49:37 GMT"), None);
+    }
+}
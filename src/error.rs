@@ -0,0 +1,76 @@
+use std::{error, fmt, io};
+
+use crate::rest_server::HttpMethod;
+
+/// ServerError describes what went wrong in a call to the server's API or in
+/// a handler.
+///
+/// Handlers return it too: an I/O error converts with `?`, and the variant
+/// decides how the request is answered unless the server's error handler is
+/// set. `BadRequest` gets `400 Bad Request`; everything else gets
+/// `500 Internal Server Error`.
+#[derive(Debug)]
+pub enum ServerError {
+    /// A listening socket could not be bound to `addr`.
+    BindFailed { addr: String, source: io::Error },
+    /// A handler was already registered for the method and path.
+    DuplicateRoute { method: HttpMethod, path: String },
+    /// A server setting or route template is invalid.
+    InvalidConfig(String),
+    /// The request could not be understood, e.g. its body is malformed.
+    BadRequest(String),
+    /// A handler failed for a reason other than I/O.
+    HandlerError(String),
+    /// Any other I/O failure.
+    Io(io::Error),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ServerError::BindFailed { addr, source } => {
+                write!(f, "failed to bind {addr}: {source}")
+            }
+            ServerError::DuplicateRoute { method, path } => {
+                write!(f, "{method} path [{path}]: attempted to set handler twice")
+            }
+            ServerError::InvalidConfig(reason) => write!(f, "invalid configuration: {reason}"),
+            ServerError::BadRequest(reason) => write!(f, "bad request: {reason}"),
+            ServerError::HandlerError(reason) => write!(f, "handler failed: {reason}"),
+            ServerError::Io(err) => write!(f, "{err}"),
+        };
+    }
+}
+
+impl error::Error for ServerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        return match self {
+            ServerError::BindFailed { source, .. } => Some(source),
+            ServerError::Io(err) => Some(err),
+            _ => None,
+        };
+    }
+}
+
+impl From<io::Error> for ServerError {
+    fn from(err: io::Error) -> Self {
+        return ServerError::Io(err);
+    }
+}
+
+/// Lets code that works with I/O errors, such as a streamed body or a
+/// WebSocket handler, use `?` on calls that return a `ServerError`.
+impl From<ServerError> for io::Error {
+    fn from(err: ServerError) -> Self {
+        let kind = match err {
+            ServerError::Io(err) => return err,
+            ServerError::BindFailed { ref source, .. } => source.kind(),
+            ServerError::BadRequest(_) => io::ErrorKind::InvalidData,
+            ServerError::DuplicateRoute { .. } | ServerError::InvalidConfig(_) => {
+                io::ErrorKind::InvalidInput
+            }
+            ServerError::HandlerError(_) => io::ErrorKind::Other,
+        };
+        return io::Error::new(kind, err);
+    }
+}
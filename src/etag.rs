@@ -0,0 +1,43 @@
+/// Computes a weak entity tag for a body, like `W/"1a-9f2c3e4b5d6a7f80"`.
+/// Equal bodies always get equal tags, so a client revalidating with
+/// `If-None-Match` gets `304 Not Modified` while the body is unchanged.
+pub fn weak_etag(body: &[u8]) -> String {
+    // 64-bit FNV-1a: fast, and collisions only cost a needless full response
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in body {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return format!("W/\"{0:x}-{hash:016x}\"", body.len());
+}
+
+/// Returns true if an `If-None-Match` value lists `etag` or is `*`, using
+/// the weak comparison, which ignores the `W/` prefix.
+pub(crate) fn none_match_hit(if_none_match: &str, etag: &str) -> bool {
+    let if_none_match = if_none_match.trim();
+    if if_none_match == "*" {
+        return true;
+    }
+    let target = match opaque_tag(etag.trim()) {
+        Some((tag, _)) => tag,
+        None => return false,
+    };
+    let mut rest = if_none_match;
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ',']);
+        match opaque_tag(rest) {
+            Some((tag, _)) if tag == target => return true,
+            Some((_, after)) => rest = after,
+            None => return false,
+        }
+    }
+}
+
+/// Splits an entity tag such as `W/"abc"` off the start of `s`, returning
+/// its quoted part and what follows it.
+fn opaque_tag(s: &str) -> Option<(&str, &str)> {
+    let s = s.strip_prefix("W/").unwrap_or(s);
+    let inner = s.strip_prefix('"')?;
+    let end = inner.find('"')?;
+    return Some((&s[..end + 2], &inner[end + 1..]));
+}
@@ -0,0 +1,37 @@
+pub mod access_log;
+mod base64;
+mod body;
+#[cfg(feature = "compression")]
+mod compression;
+mod connection;
+pub mod cookie;
+pub mod cors;
+mod date;
+pub mod error;
+pub mod etag;
+mod macros;
+pub mod metrics;
+pub mod middleware;
+pub mod mime;
+pub mod multipart;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+mod proxy;
+pub mod rate_limit;
+mod request_id;
+pub mod rest_server;
+pub mod route_group;
+mod router;
+pub mod security_headers;
+#[cfg(feature = "websocket")]
+mod sha1;
+pub mod shutdown;
+mod state;
+mod static_files;
+pub mod streaming;
+mod thread_pool;
+#[cfg(feature = "tls")]
+mod tls;
+pub mod url;
+#[cfg(feature = "websocket")]
+pub mod websocket;
@@ -0,0 +1,56 @@
+/// Registers a table of routes on a server in one block, returning the first
+/// registration error, such as a route registered twice. Each row is a
+/// method, a path and a handler, as passed to `RestServer::register`:
+///
+/// ```
+/// use rustful::rest_server::{self, HttpRequest, RestServer};
+/// use rustful::routes;
+///
+/// fn create_user(_req: HttpRequest) -> rest_server::HandlerResult {
+///     return rest_server::created("created");
+/// }
+///
+/// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+/// routes!(svr, {
+///     GET "/ping" => rest_server::handle_ping,
+///     POST "/users" => create_user,
+///     DELETE "/users/:id" => |req| Ok(format!("deleted {0}", req.param("id").unwrap())),
+/// })
+/// .unwrap();
+///
+/// let err = routes!(svr, { GET "/ping" => rest_server::handle_ping }).unwrap_err();
+/// assert!(err.to_string().contains("/ping"));
+/// ```
+///
+/// The table above expands to one `register` call per row, stopping at the
+/// first that fails:
+///
+/// ```ignore
+/// 'routes: {
+///     if let Err(err) = svr.register(HttpMethod::GET, "/ping", rest_server::handle_ping) {
+///         break 'routes Err(err);
+///     }
+///     if let Err(err) = svr.register(HttpMethod::POST, "/users", create_user) {
+///         break 'routes Err(err);
+///     }
+///     // ...
+///     Ok(())
+/// }
+/// ```
+#[macro_export]
+macro_rules! routes {
+    ($server:expr, { $($method:ident $path:literal => $handler:expr),* $(,)? }) => {
+        'routes: {
+            $(
+                if let Err(err) = $server.register(
+                    $crate::rest_server::HttpMethod::$method,
+                    $path,
+                    $handler,
+                ) {
+                    break 'routes Err(err);
+                }
+            )*
+            Ok::<(), $crate::error::ServerError>(())
+        }
+    };
+}
@@ -1,10 +1,12 @@
-mod rest_server;
-use crate::rest_server::RestServer;
+use rustful::rest_server::{self, RestServer};
 
 fn main() {
     let mut svr = RestServer::new("sample-server", "127.0.0.1", 8080).unwrap();
     svr.register_path("/ping", rest_server::handle_ping)
         .unwrap();
 
-    let _ = svr.listen().unwrap();
+    if let Err(err) = svr.listen() {
+        eprintln!("sample-server: {err}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Metrics is a snapshot of a server's load counters, returned by
+/// `RestServer::metrics`. Counts are totals since the server was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Metrics {
+    /// Connections currently open.
+    pub active_connections: usize,
+    /// Requests currently being handled.
+    pub in_flight_requests: usize,
+    /// Responses written, including errors for unparseable requests.
+    pub requests_total: u64,
+    pub responses_1xx: u64,
+    pub responses_2xx: u64,
+    pub responses_3xx: u64,
+    pub responses_4xx: u64,
+    pub responses_5xx: u64,
+    /// Response body bytes written, as sent on the wire.
+    pub response_bytes_total: u64,
+}
+
+/// Counters updated by the worker threads.
+pub(crate) struct ServerMetrics {
+    active_connections: AtomicUsize,
+    in_flight_requests: AtomicUsize,
+    requests_total: AtomicU64,
+    /// Responses by status class, indexed by the first digit minus one.
+    responses: [AtomicU64; 5],
+    response_bytes: AtomicU64,
+}
+
+impl ServerMetrics {
+    pub(crate) fn new() -> Self {
+        return ServerMetrics {
+            active_connections: AtomicUsize::new(0),
+            in_flight_requests: AtomicUsize::new(0),
+            requests_total: AtomicU64::new(0),
+            responses: Default::default(),
+            response_bytes: AtomicU64::new(0),
+        };
+    }
+
+    /// Counts a connection as active until the returned guard is dropped.
+    pub(crate) fn open_connection(&self) -> GaugeGuard<'_> {
+        return GaugeGuard::new(&self.active_connections);
+    }
+
+    /// Counts a request as in flight until the returned guard is dropped.
+    pub(crate) fn start_request(&self) -> GaugeGuard<'_> {
+        return GaugeGuard::new(&self.in_flight_requests);
+    }
+
+    /// Counts one response with the given status code.
+    pub(crate) fn record_response(&self, status: u16) {
+        let _ = self.requests_total.fetch_add(1, Ordering::Relaxed);
+        if let Some(class) = self
+            .responses
+            .get(usize::from(status / 100).wrapping_sub(1))
+        {
+            let _ = class.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts body bytes written for a response.
+    pub(crate) fn record_bytes(&self, bytes: usize) {
+        let _ = self
+            .response_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        let class = |i: usize| self.responses[i].load(Ordering::Relaxed);
+        return Metrics {
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            in_flight_requests: self.in_flight_requests.load(Ordering::Relaxed),
+            requests_total: self.requests_total.load(Ordering::Relaxed),
+            responses_1xx: class(0),
+            responses_2xx: class(1),
+            responses_3xx: class(2),
+            responses_4xx: class(3),
+            responses_5xx: class(4),
+            response_bytes_total: self.response_bytes.load(Ordering::Relaxed),
+        };
+    }
+}
+
+/// Decrements a gauge when dropped, so that early returns and errors are
+/// accounted for.
+pub(crate) struct GaugeGuard<'a> {
+    gauge: &'a AtomicUsize,
+}
+
+impl<'a> GaugeGuard<'a> {
+    pub(crate) fn new(gauge: &'a AtomicUsize) -> Self {
+        let _ = gauge.fetch_add(1, Ordering::Relaxed);
+        return GaugeGuard { gauge };
+    }
+
+    /// Increments the gauge unless it has already reached `max`.
+    pub(crate) fn try_new(gauge: &'a AtomicUsize, max: usize) -> Option<Self> {
+        gauge
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()?;
+        return Some(GaugeGuard { gauge });
+    }
+}
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.gauge.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::Instant;
+
+use crate::error::ServerError;
+use crate::rest_server::{HttpRequest, HttpResponse};
+
+/// The rest of the chain a middleware wraps: the middlewares registered after
+/// it, and finally the routing step and matched handler.
+pub type Next<'a> = &'a dyn Fn(HttpRequest) -> Result<HttpResponse, ServerError>;
+
+/// Middleware runs around request handling, for cross-cutting concerns such
+/// as logging, authentication or timing.
+///
+/// A middleware may inspect or change the request before passing it to
+/// `next`, change the response `next` returns, or answer on its own without
+/// calling `next` at all. Middlewares registered with
+/// `RestServer::use_middleware` compose in registration order: the first one
+/// registered is the outermost.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, req: HttpRequest, next: Next) -> Result<HttpResponse, ServerError>;
+}
+
+/// Any function or closure with the right signature is a middleware.
+impl<F> Middleware for F
+where
+    F: Fn(HttpRequest, Next) -> Result<HttpResponse, ServerError> + Send + Sync,
+{
+    fn handle(&self, req: HttpRequest, next: Next) -> Result<HttpResponse, ServerError> {
+        return self(req, next);
+    }
+}
+
+/// Runs `req` through `chain` and then `endpoint`.
+pub(crate) fn run_chain(
+    chain: &[Box<dyn Middleware>],
+    req: HttpRequest,
+    endpoint: Next,
+) -> Result<HttpResponse, ServerError> {
+    return match chain.split_first() {
+        None => endpoint(req),
+        Some((first, rest)) => first.handle(req, &|req| run_chain(rest, req, endpoint)),
+    };
+}
+
+/// LoggingMiddleware prints the method, path, status and duration of every
+/// request.
+pub struct LoggingMiddleware;
+
+impl Middleware for LoggingMiddleware {
+    fn handle(&self, req: HttpRequest, next: Next) -> Result<HttpResponse, ServerError> {
+        let method = req.method();
+        let path = req.path().to_string();
+        let start = Instant::now();
+        let resp = next(req);
+        let status = match &resp {
+            Ok(r) => r.status().to_string(),
+            Err(_) => "error".to_string(),
+        };
+        println!(
+            "{method} {path} -> {status} ({0} ms)",
+            start.elapsed().as_millis()
+        );
+        return resp;
+    }
+}
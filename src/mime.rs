@@ -0,0 +1,167 @@
+/// Returns the MIME type for a file extension, with or without its leading
+/// dot. The match is case-insensitive and unknown extensions map to
+/// `application/octet-stream`.
+pub fn mime_for_extension(ext: &str) -> &'static str {
+    let ext = ext.strip_prefix('.').unwrap_or(ext).to_ascii_lowercase();
+    return match ext.as_str() {
+        // Text
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "xml" => "application/xml",
+        // Data
+        "json" => "application/json",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        // Images
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "webp" => "image/webp",
+        // Fonts
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        // Audio and video
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    };
+}
+
+/// Guesses the MIME type of a body from its shape: JSON objects and arrays,
+/// HTML documents, plain text otherwise, and `application/octet-stream` if
+/// it is not valid UTF-8.
+pub fn mime_for_body(body: &[u8]) -> &'static str {
+    let body = match std::str::from_utf8(body) {
+        Ok(body) => body,
+        Err(_) => return "application/octet-stream",
+    };
+    let trimmed = body.trim_start();
+    if (trimmed.starts_with('{') && body.trim_end().ends_with('}'))
+        || (trimmed.starts_with('[') && body.trim_end().ends_with(']'))
+    {
+        return "application/json";
+    }
+    let head = trimmed.get(..15).unwrap_or(trimmed).to_ascii_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return "text/html; charset=utf-8";
+    }
+    return "text/plain; charset=utf-8";
+}
+
+/// Picks the type from `offered` that an `Accept` header value such as
+/// `text/html, application/json;q=0.9, */*;q=0.1` prefers, or `None` if the
+/// client accepts none of them. Each offered type is weighted by the most
+/// specific range matching it, so `text/html` beats `text/*` beats `*/*`.
+/// Ties go to the type offered first, and `q=0` rules a type out.
+///
+/// ```
+/// use rustful::mime::best_match;
+///
+/// let accept = "text/html, application/json;q=0.9";
+/// assert_eq!(best_match(accept, &["application/json", "text/html"]), Some("text/html"));
+/// assert_eq!(best_match("text/*;q=0.5", &["text/plain"]), Some("text/plain"));
+/// assert_eq!(best_match("image/png", &["text/plain"]), None);
+/// ```
+pub fn best_match<'a>(accept: &str, offered: &[&'a str]) -> Option<&'a str> {
+    let ranges: Vec<MediaRange> = accept.split(',').filter_map(MediaRange::parse).collect();
+    let mut best: Option<(&'a str, f32)> = None;
+    for &mime in offered {
+        let essence = mime
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        let (kind, subtype) = match essence.split_once('/') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let q = ranges
+            .iter()
+            .filter(|r| r.matches(kind, subtype))
+            .max_by_key(|r| r.specificity())
+            .map_or(0.0, |r| r.q);
+        if q > 0.0 && best.map_or(true, |(_, best_q)| q > best_q) {
+            best = Some((mime, q));
+        }
+    }
+    return best.map(|(mime, _)| mime);
+}
+
+/// One entry of an `Accept` header, e.g. `text/*;q=0.5`.
+struct MediaRange {
+    kind: String,
+    subtype: String,
+    q: f32,
+}
+
+impl MediaRange {
+    fn parse(item: &str) -> Option<MediaRange> {
+        let mut parts = item.split(';');
+        let essence = parts.next()?.trim().to_ascii_lowercase();
+        let (kind, subtype) = essence.split_once('/')?;
+        if kind.is_empty() || subtype.is_empty() || (kind == "*" && subtype != "*") {
+            return None;
+        }
+        let q = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0)
+            .clamp(0.0, 1.0);
+        return Some(MediaRange {
+            kind: kind.to_string(),
+            subtype: subtype.to_string(),
+            q,
+        });
+    }
+
+    fn matches(&self, kind: &str, subtype: &str) -> bool {
+        return (self.kind == "*" || self.kind == kind)
+            && (self.subtype == "*" || self.subtype == subtype);
+    }
+
+    /// `*/*` is 0, `text/*` is 1 and `text/html` is 2.
+    fn specificity(&self) -> u8 {
+        return u8::from(self.kind != "*") + u8::from(self.subtype != "*");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mime_for_extension;
+
+    #[test]
+    fn maps_common_extensions() {
+        let cases = [
+            ("html", "text/html; charset=utf-8"),
+            ("css", "text/css; charset=utf-8"),
+            ("js", "text/javascript; charset=utf-8"),
+            ("json", "application/json"),
+            ("png", "image/png"),
+            ("jpg", "image/jpeg"),
+            ("gif", "image/gif"),
+            ("svg", "image/svg+xml"),
+            ("wasm", "application/wasm"),
+            ("pdf", "application/pdf"),
+            ("txt", "text/plain; charset=utf-8"),
+            ("unknown", "application/octet-stream"),
+            ("", "application/octet-stream"),
+            (".png", "image/png"),
+            ("PNG", "image/png"),
+            ("Html", "text/html; charset=utf-8"),
+        ];
+        for (ext, mime) in cases {
+            assert_eq!(mime_for_extension(ext), mime, "extension {ext:?}");
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use crate::error::ServerError;
+
+/// Multipart iterates over the parts of a `multipart/form-data` body.
+/// Created by `HttpRequest::multipart`.
+///
+/// Each item is a part, or an error if the body is malformed, after which the
+/// iterator ends.
+pub struct Multipart<'a> {
+    delimiter: Vec<u8>,
+    /// The body following the last delimiter seen, or `None` once done.
+    rest: Option<&'a [u8]>,
+}
+
+/// Part is one field or file of a `multipart/form-data` body.
+pub struct Part<'a> {
+    headers: HashMap<String, String>,
+    name: Option<String>,
+    filename: Option<String>,
+    data: &'a [u8],
+}
+
+impl<'a> Part<'a> {
+    /// Returns the value of the named part header, such as `Content-Type`.
+    /// The lookup is case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return self
+            .headers
+            .get(&name.to_ascii_lowercase())
+            .map(|v| v.as_str());
+    }
+
+    /// Returns the form field name from `Content-Disposition`.
+    pub fn name(&self) -> Option<&str> {
+        return self.name.as_deref();
+    }
+
+    /// Returns the file name from `Content-Disposition`, if the part is a
+    /// file upload.
+    pub fn filename(&self) -> Option<&str> {
+        return self.filename.as_deref();
+    }
+
+    /// Returns the content of the part.
+    pub fn data(&self) -> &'a [u8] {
+        return self.data;
+    }
+}
+
+impl<'a> Multipart<'a> {
+    /// Prepares to parse `body` with the boundary from a `Content-Type`
+    /// value. Fails if the content type is not `multipart/form-data` or has
+    /// no boundary.
+    pub(crate) fn new(content_type: Option<&str>, body: &'a [u8]) -> Result<Self, ServerError> {
+        let content_type = content_type.unwrap_or("");
+        let mut params = content_type.split(';');
+        let mime = params.next().unwrap_or("").trim();
+        if !mime.eq_ignore_ascii_case("multipart/form-data") {
+            return Err(ServerError::BadRequest(
+                "HTTP request body is not multipart: expected Content-Type multipart/form-data"
+                    .to_string(),
+            ));
+        }
+        let boundary = params
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("boundary"))
+            .map(|(_, v)| unquote(v.trim()))
+            .filter(|b| !b.is_empty() && b.len() <= 70);
+        let boundary = match boundary {
+            Some(boundary) => boundary,
+            None => {
+                return Err(ServerError::BadRequest(
+                    "multipart Content-Type has no valid boundary".to_string(),
+                ))
+            }
+        };
+        let delimiter = format!("--{boundary}").into_bytes();
+
+        // Skip the preamble up to and including the first delimiter
+        let rest = match find(body, &delimiter) {
+            Some(i) => &body[i + delimiter.len()..],
+            None => return Err(malformed("missing opening boundary")),
+        };
+        return Ok(Multipart {
+            delimiter,
+            rest: Some(rest),
+        });
+    }
+
+    /// Parses the part starting right after a delimiter, returning it and the
+    /// input following its closing delimiter.
+    fn parse_part(&self, rest: &'a [u8]) -> Result<Option<(Part<'a>, &'a [u8])>, ServerError> {
+        if rest.starts_with(b"--") {
+            // The closing `--boundary--`; anything after it is epilogue
+            return Ok(None);
+        }
+        let rest = match rest.strip_prefix(b"\r\n") {
+            Some(rest) => rest,
+            None => return Err(malformed("boundary not followed by CRLF")),
+        };
+        let head_end = match find(rest, b"\r\n\r\n") {
+            Some(i) => i,
+            None => return Err(malformed("unterminated part headers")),
+        };
+        let head = match std::str::from_utf8(&rest[..head_end]) {
+            Ok(head) => head,
+            Err(_) => return Err(malformed("part headers are not valid UTF-8")),
+        };
+        let mut headers: HashMap<String, String> = HashMap::new();
+        for line in head.split("\r\n") {
+            let (name, value) = match line.split_once(':') {
+                Some(pair) => pair,
+                None => return Err(malformed("invalid part header line")),
+            };
+            let _ = headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+
+        let content = &rest[head_end + 4..];
+        let mut closing = b"\r\n".to_vec();
+        closing.extend_from_slice(&self.delimiter);
+        let data_end = match find(content, &closing) {
+            Some(i) => i,
+            None => return Err(malformed("missing closing boundary")),
+        };
+
+        let (name, filename) = match headers.get("content-disposition") {
+            Some(value) => disposition_names(value),
+            None => (None, None),
+        };
+        let part = Part {
+            headers,
+            name,
+            filename,
+            data: &content[..data_end],
+        };
+        return Ok(Some((part, &content[data_end + closing.len()..])));
+    }
+}
+
+impl<'a> Iterator for Multipart<'a> {
+    type Item = Result<Part<'a>, ServerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest.take()?;
+        return match self.parse_part(rest) {
+            Ok(Some((part, rest))) => {
+                self.rest = Some(rest);
+                Some(Ok(part))
+            }
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        };
+    }
+}
+
+/// Extracts the `name` and `filename` parameters of a `Content-Disposition`
+/// value such as `form-data; name="file"; filename="a.txt"`.
+fn disposition_names(value: &str) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut filename = None;
+    for param in value.split(';').skip(1) {
+        if let Some((key, val)) = param.split_once('=') {
+            match key.trim().to_ascii_lowercase().as_str() {
+                "name" => name = Some(unquote(val.trim())),
+                "filename" => filename = Some(unquote(val.trim())),
+                _ => {}
+            }
+        }
+    }
+    return (name, filename);
+}
+
+fn unquote(value: &str) -> String {
+    return value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string();
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    return haystack.windows(needle.len()).position(|w| w == needle);
+}
+
+fn malformed(reason: &str) -> ServerError {
+    return ServerError::BadRequest(format!("malformed multipart body: {reason}"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Multipart;
+
+    #[test]
+    fn parses_a_file_and_a_field() {
+        let body = b"preamble\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"upload\"; filename=\"notes.txt\"\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            line one\r\nline two\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\
+            \r\n\
+            My notes\r\n\
+            --XyZ--\r\n";
+        let parts = Multipart::new(Some("multipart/form-data; boundary=XyZ"), body)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(parts.len(), 2);
+
+        let file = &parts[0];
+        assert_eq!(file.name(), Some("upload"));
+        assert_eq!(file.filename(), Some("notes.txt"));
+        assert_eq!(file.header("content-type"), Some("text/plain"));
+        assert_eq!(file.data(), b"line one\r\nline two");
+
+        let field = &parts[1];
+        assert_eq!(field.name(), Some("title"));
+        assert_eq!(field.filename(), None);
+        assert_eq!(field.header("Content-Type"), None);
+        assert_eq!(field.data(), b"My notes");
+    }
+}
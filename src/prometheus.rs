@@ -0,0 +1,136 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::error::ServerError;
+use crate::metrics::Metrics;
+
+/// PrometheusOptions configures the endpoint added by
+/// `RestServer::enable_prometheus`.
+#[derive(Debug, Clone)]
+pub struct PrometheusOptions {
+    /// Path the metrics are served at. Defaults to `/metrics`.
+    pub path: String,
+    /// Include `http_requests_total`, also broken down by status class, and
+    /// `http_response_bytes_total`.
+    pub requests: bool,
+    /// Include `http_requests_in_flight`.
+    pub in_flight: bool,
+    /// Include the `http_request_duration_seconds` histogram.
+    pub duration: bool,
+    /// Upper bounds of the duration histogram buckets, in seconds, in
+    /// increasing order. A `+Inf` bucket is always added.
+    pub buckets: Vec<f64>,
+}
+
+impl Default for PrometheusOptions {
+    fn default() -> Self {
+        return PrometheusOptions {
+            path: "/metrics".to_string(),
+            requests: true,
+            in_flight: true,
+            duration: true,
+            // The default buckets of the Prometheus client libraries
+            buckets: vec![
+                0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+            ],
+        };
+    }
+}
+
+/// A request-duration histogram updated by the worker threads.
+pub(crate) struct Histogram {
+    bounds: Vec<f64>,
+    /// Observations per bucket, not cumulative; the last one is `+Inf`.
+    counts: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    /// Creates a histogram, failing if the bounds are not finite and
+    /// strictly increasing.
+    pub(crate) fn new(bounds: &[f64]) -> Result<Self, ServerError> {
+        if bounds.iter().any(|b| !b.is_finite()) || bounds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(ServerError::InvalidConfig(format!(
+                "histogram buckets must be finite and increasing: {bounds:?}"
+            )));
+        }
+        return Ok(Histogram {
+            bounds: bounds.to_vec(),
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+        });
+    }
+
+    pub(crate) fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let i = self.bounds.partition_point(|&b| b < secs);
+        let _ = self.counts[i].fetch_add(1, Ordering::Relaxed);
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let _ = self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+}
+
+/// Renders the selected metrics in the Prometheus text exposition format.
+pub(crate) fn render(
+    options: &PrometheusOptions,
+    metrics: &Metrics,
+    histogram: Option<&Histogram>,
+) -> String {
+    let mut out = String::new();
+    if options.requests {
+        out.push_str("# HELP http_requests_total Total number of HTTP responses sent.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        let _ = writeln!(out, "http_requests_total {0}", metrics.requests_total);
+        out.push_str("# HELP http_responses_total HTTP responses sent by status class.\n");
+        out.push_str("# TYPE http_responses_total counter\n");
+        let classes = [
+            ("1xx", metrics.responses_1xx),
+            ("2xx", metrics.responses_2xx),
+            ("3xx", metrics.responses_3xx),
+            ("4xx", metrics.responses_4xx),
+            ("5xx", metrics.responses_5xx),
+        ];
+        for (class, count) in classes {
+            let _ = writeln!(out, "http_responses_total{{class=\"{class}\"}} {count}");
+        }
+        out.push_str("# HELP http_response_bytes_total Total HTTP response body bytes sent.\n");
+        out.push_str("# TYPE http_response_bytes_total counter\n");
+        let _ = writeln!(
+            out,
+            "http_response_bytes_total {0}",
+            metrics.response_bytes_total
+        );
+    }
+    if options.in_flight {
+        out.push_str("# HELP http_requests_in_flight HTTP requests being handled.\n");
+        out.push_str("# TYPE http_requests_in_flight gauge\n");
+        let _ = writeln!(
+            out,
+            "http_requests_in_flight {0}",
+            metrics.in_flight_requests
+        );
+    }
+    if let Some(histogram) = histogram {
+        out.push_str("# HELP http_request_duration_seconds Time taken to handle HTTP requests.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        let mut cumulative: u64 = 0;
+        for (i, count) in histogram.counts.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let le = match histogram.bounds.get(i) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{le=\"{le}\"}} {cumulative}"
+            );
+        }
+        let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "http_request_duration_seconds_sum {sum}");
+        let _ = writeln!(out, "http_request_duration_seconds_count {cumulative}");
+    }
+    return out;
+}
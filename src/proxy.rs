@@ -0,0 +1,370 @@
+use std::{
+    io::{self, BufRead, BufReader, Error, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::body::TrailerLimits;
+use crate::error::ServerError;
+use crate::rest_server::{has_token, is_valid_header, HttpMethod, HttpRequest, HttpResponse};
+use crate::streaming::BodyReader;
+use crate::url::encode_path;
+
+/// How long to wait for the upstream to accept a connection, and then for
+/// each read from or write to it.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The most header lines accepted in an upstream response.
+const MAX_UPSTREAM_HEADERS: usize = 100;
+
+/// The longest status or header line accepted in an upstream response.
+const MAX_UPSTREAM_LINE: u64 = 8 * 1024;
+
+/// Limits on the trailer fields of a chunked upstream response.
+const UPSTREAM_TRAILER_LIMITS: TrailerLimits = TrailerLimits {
+    max_fields: MAX_UPSTREAM_HEADERS,
+    max_bytes: MAX_UPSTREAM_HEADERS * MAX_UPSTREAM_LINE as usize,
+};
+
+/// Headers that only describe a single connection and are never forwarded
+/// (RFC 9110, section 7.6.1).
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Proxy forwards requests under `url_prefix` to an HTTP upstream, with the
+/// prefix replaced by the upstream's base path.
+pub(crate) struct Proxy {
+    server_name: String,
+    url_prefix: String,
+    /// `host:port` to connect to, also sent as `Host`.
+    authority: String,
+    /// Path the rest of the request path is appended to, without a trailing
+    /// slash.
+    base_path: String,
+}
+
+/// How the body of an upstream response is framed.
+enum Framing {
+    Length(usize),
+    Chunked,
+    /// The body ends when the upstream closes the connection.
+    Close,
+}
+
+impl Proxy {
+    /// Parses `upstream_base`, which must look like `http://host[:port][/path]`.
+    pub(crate) fn new(
+        server_name: &str,
+        url_prefix: &str,
+        upstream_base: &str,
+    ) -> Result<Self, ServerError> {
+        let invalid = |reason: &str| {
+            return ServerError::InvalidConfig(format!(
+                "HttpServer [{server_name}] proxy upstream [{upstream_base}]: {reason}"
+            ));
+        };
+        let rest = match upstream_base.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => rest,
+            Some((scheme, _)) => {
+                return Err(invalid(&format!("unsupported scheme {scheme:?}")));
+            }
+            None => return Err(invalid("expected a URL such as http://host:port")),
+        };
+        if rest.contains(['?', '#']) {
+            return Err(invalid("a query or fragment is not allowed"));
+        }
+        let (authority, base_path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        if authority.is_empty() || authority.contains('@') {
+            return Err(invalid("expected a host, optionally with a port"));
+        }
+        // An IPv6 address has colons of its own, so only a colon after the
+        // closing bracket starts the port
+        let authority = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => {
+                let port = &authority[i + 1..];
+                if port.parse::<u16>().is_err() {
+                    return Err(invalid(&format!("invalid port {port:?}")));
+                }
+                authority.to_string()
+            }
+            _ => format!("{authority}:80"),
+        };
+        return Ok(Proxy {
+            server_name: server_name.to_string(),
+            url_prefix: url_prefix.trim_end_matches('/').to_string(),
+            authority,
+            base_path: base_path.trim_end_matches('/').to_string(),
+        });
+    }
+
+    /// Forwards the request and streams back the upstream's response.
+    /// Failing to reach the upstream, or a response that cannot be parsed,
+    /// is answered with `502 Bad Gateway`, and a timeout with
+    /// `504 Gateway Timeout`.
+    pub(crate) fn serve(&self, req: HttpRequest) -> Result<HttpResponse, ServerError> {
+        return match self.forward(&req) {
+            Ok(resp) => Ok(resp),
+            Err(err) => {
+                println!(
+                    "RestServer [{0}]: proxy to {1} failed: {err}",
+                    self.server_name, self.authority
+                );
+                Ok(match err.kind() {
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+                        HttpResponse::new(504, "Gateway Timeout")
+                    }
+                    _ => HttpResponse::new(502, "Bad Gateway"),
+                })
+            }
+        };
+    }
+
+    fn forward(&self, req: &HttpRequest) -> Result<HttpResponse, Error> {
+        let mut stream = self.connect()?;
+        stream.write_all(&self.request_head(req))?;
+        stream.write_all(req.body())?;
+        stream.flush()?;
+
+        let mut reader = BufReader::new(stream);
+        let (status, headers) = loop {
+            let (status, headers) = read_head(&mut reader)?;
+            // Interim responses are not passed on; the final one follows
+            if !(100..200).contains(&status) || status == 101 {
+                break (status, headers);
+            }
+        };
+        if status == 101 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "upstream switched protocols, which is not supported",
+            ));
+        }
+
+        let framing = framing(&headers)?;
+        let connection = header(&headers, "connection").unwrap_or("").to_string();
+        let has_body = req.method() != HttpMethod::HEAD && !matches!(status, 204 | 304);
+        let mut resp = if has_body {
+            let mut resp = HttpResponse::stream(move |writer| {
+                return copy_body(&mut reader, framing, writer);
+            });
+            resp.set_status(status);
+            resp
+        } else {
+            let mut resp = HttpResponse::new(status, "");
+            // A HEAD response describes the body a GET would have returned
+            if let Framing::Length(len) = framing {
+                resp.set_head_length(len);
+            }
+            resp
+        };
+        for (name, value) in &headers {
+            if is_hop_by_hop(name, &connection)
+                || name.eq_ignore_ascii_case("content-length")
+                || !is_valid_header(name, value)
+            {
+                continue;
+            }
+            // Repeated headers are joined, except for cookies, which cannot be
+            if name.eq_ignore_ascii_case("set-cookie") {
+                resp.add_raw_cookie(value);
+                continue;
+            }
+            let joined = match resp.header(name) {
+                Some(previous) => format!("{previous}, {value}"),
+                None => value.clone(),
+            };
+            resp.set_header(name, &joined);
+        }
+        return Ok(resp);
+    }
+
+    fn connect(&self) -> Result<TcpStream, Error> {
+        let mut last_err = Error::new(ErrorKind::NotFound, "upstream host has no address");
+        for addr in self.authority.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, UPSTREAM_TIMEOUT) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(UPSTREAM_TIMEOUT))?;
+                    stream.set_write_timeout(Some(UPSTREAM_TIMEOUT))?;
+                    return Ok(stream);
+                }
+                Err(err) => last_err = err,
+            }
+        }
+        return Err(last_err);
+    }
+
+    /// Builds the request line and headers sent upstream. The client's
+    /// headers are copied except for hop-by-hop ones, `Host` names the
+    /// upstream, the client's address is appended to `X-Forwarded-For` and the
+    /// request ID is passed on in `X-Request-Id`. `Expect` is dropped, since
+    /// the body has already been read. The upstream connection is used for
+    /// this request only.
+    fn request_head(&self, req: &HttpRequest) -> Vec<u8> {
+        let rest = req.path().strip_prefix(&self.url_prefix).unwrap_or("");
+        let mut target = encode_path(&format!("{0}{rest}", self.base_path));
+        if !target.starts_with('/') {
+            target.insert(0, '/');
+        }
+        if !req.query_string().is_empty() {
+            target.push('?');
+            target.push_str(req.query_string());
+        }
+
+        let mut head = format!("{0} {target} HTTP/1.1\r\n", req.method().as_str());
+        head.push_str(&format!("Host: {0}\r\n", self.authority));
+        let connection = req.header("connection").unwrap_or("");
+        let mut forwarded_for: Option<&str> = None;
+        for (name, value) in req.headers() {
+            match name {
+                "host" | "content-length" | "expect" | "x-request-id" => continue,
+                "x-forwarded-for" => forwarded_for = Some(value),
+                _ if is_hop_by_hop(name, connection) => continue,
+                _ => head.push_str(&format!("{name}: {value}\r\n")),
+            }
+        }
+        let client = req.remote_addr().ip();
+        match forwarded_for {
+            Some(previous) => head.push_str(&format!("X-Forwarded-For: {previous}, {client}\r\n")),
+            None => head.push_str(&format!("X-Forwarded-For: {client}\r\n")),
+        }
+        if !req.body().is_empty() || !matches!(req.method(), HttpMethod::GET | HttpMethod::HEAD) {
+            head.push_str(&format!("Content-Length: {0}\r\n", req.body().len()));
+        }
+        // The upstream can tag its logs with the same ID
+        head.push_str(&format!("X-Request-Id: {0}\r\n", req.request_id()));
+        head.push_str("Connection: close\r\n\r\n");
+        return head.into_bytes();
+    }
+}
+
+/// Returns true if the header must not be forwarded: a standard hop-by-hop
+/// header or one named in the message's `Connection` header.
+fn is_hop_by_hop(name: &str, connection: &str) -> bool {
+    return HOP_BY_HOP.iter().any(|h| h.eq_ignore_ascii_case(name)) || has_token(connection, name);
+}
+
+/// Looks up a header of an upstream response by name, case-insensitively.
+fn header<'h>(headers: &'h [(String, String)], name: &str) -> Option<&'h str> {
+    return headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str());
+}
+
+/// Reads the status line and headers of an upstream response. Header names
+/// keep their case and repeated headers stay separate.
+fn read_head(reader: &mut impl BufRead) -> Result<(u16, Vec<(String, String)>), Error> {
+    let status_line = read_line(reader)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let status = match (parts.next(), parts.next()) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/1.") => code.parse::<u16>().ok(),
+        _ => None,
+    };
+    let status = match status {
+        Some(status) if (100..600).contains(&status) => status,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid upstream status line: {status_line:?}"),
+            ))
+        }
+    };
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_UPSTREAM_HEADERS {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "upstream sent too many headers",
+            ));
+        }
+        match line.split_once(':') {
+            Some((name, value)) => {
+                headers.push((name.trim().to_string(), value.trim().to_string()))
+            }
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("malformed upstream header line: {line:?}"),
+                ))
+            }
+        }
+    }
+    return Ok((status, headers));
+}
+
+/// Reads one line of an upstream response head, without its line break.
+fn read_line(reader: &mut impl BufRead) -> Result<String, Error> {
+    let mut line: Vec<u8> = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_UPSTREAM_LINE)
+        .read_until(b'\n', &mut line)?;
+    if line.last() != Some(&b'\n') {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "upstream response head was cut short or has an overlong line",
+        ));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    return String::from_utf8(line)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "upstream header is not valid UTF-8"));
+}
+
+fn framing(headers: &[(String, String)]) -> Result<Framing, Error> {
+    if let Some(encoding) = header(headers, "transfer-encoding") {
+        if !encoding.trim().to_ascii_lowercase().ends_with("chunked") {
+            return Ok(Framing::Close);
+        }
+        return Ok(Framing::Chunked);
+    }
+    return match header(headers, "content-length") {
+        Some(len) => match len.trim().parse::<usize>() {
+            Ok(len) => Ok(Framing::Length(len)),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid upstream Content-Length: {len:?}"),
+            )),
+        },
+        None => Ok(Framing::Close),
+    };
+}
+
+/// Copies the upstream response body to the client as it arrives.
+fn copy_body(
+    reader: &mut BufReader<TcpStream>,
+    framing: Framing,
+    writer: &mut dyn Write,
+) -> Result<(), Error> {
+    let _ = match framing {
+        Framing::Length(len) => io::copy(
+            &mut BodyReader::new(reader, Some(len), UPSTREAM_TRAILER_LIMITS),
+            writer,
+        )?,
+        Framing::Chunked => io::copy(
+            &mut BodyReader::new(reader, None, UPSTREAM_TRAILER_LIMITS),
+            writer,
+        )?,
+        Framing::Close => io::copy(reader, writer)?,
+    };
+    return writer.flush();
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::error::ServerError;
+use crate::middleware::{Middleware, Next};
+use crate::rest_server::{HttpRequest, HttpResponse};
+
+/// How often buckets that have refilled completely are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest wait reported in `Retry-After`, however slowly tokens refill.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets {
+    by_ip: HashMap<IpAddr, Bucket>,
+    last_sweep: Instant,
+}
+
+/// RateLimiter is a middleware that limits how fast each client IP address
+/// may send requests, using a token bucket per address.
+///
+/// Every client starts with `burst` tokens and regains `requests_per_second`
+/// of them each second, up to `burst`. A request costs one token; without
+/// one it is answered with `429 Too Many Requests` and a `Retry-After`
+/// header instead of reaching the handler.
+///
+/// ```no_run
+/// use rustful::rate_limit::RateLimiter;
+/// use rustful::rest_server::RestServer;
+///
+/// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+/// svr.use_middleware(RateLimiter::new(5.0, 20));
+/// ```
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `requests_per_second` on average per
+    /// client, with bursts of up to `burst` requests. A burst of zero is
+    /// treated as one. With a rate of zero or less, tokens never come back,
+    /// and clients that used theirs are told to retry after a day.
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        return RateLimiter {
+            rate: requests_per_second.max(f64::MIN_POSITIVE),
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(Buckets {
+                by_ip: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        };
+    }
+
+    /// Takes a token for `ip`. Returns `None` if the request may go ahead,
+    /// or how long until the next token is available.
+    fn acquire(&self, ip: IpAddr) -> Option<Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Full buckets behave exactly like missing ones, so dropping them
+        // bounds memory to the clients active in the last few seconds
+        if now.duration_since(buckets.last_sweep) >= SWEEP_INTERVAL {
+            let (rate, burst) = (self.rate, self.burst);
+            buckets.by_ip.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst
+            });
+            buckets.last_sweep = now;
+        }
+
+        let bucket = buckets.by_ip.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return None;
+        }
+        let wait = Duration::try_from_secs_f64((1.0 - bucket.tokens) / self.rate);
+        return Some(wait.map_or(MAX_RETRY_AFTER, |w| w.min(MAX_RETRY_AFTER)));
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn handle(&self, req: HttpRequest, next: Next) -> Result<HttpResponse, ServerError> {
+        return match self.acquire(req.remote_addr().ip()) {
+            None => next(req),
+            Some(wait) => {
+                let mut resp = HttpResponse::new(429, "Too Many Requests");
+                // Retry-After is in whole seconds; round up so retrying on
+                // time succeeds
+                let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                resp.set_header("Retry-After", &secs.max(1).to_string());
+                Ok(resp)
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use super::{RateLimiter, MAX_RETRY_AFTER};
+
+    #[test]
+    fn waits_for_the_next_token() {
+        let limiter = RateLimiter::new(2.0, 1);
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert_eq!(limiter.acquire(ip), None);
+        let wait = limiter.acquire(ip).unwrap();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn caps_the_wait_without_refills() {
+        for rate in [0.0, -1.0, f64::NAN] {
+            let limiter = RateLimiter::new(rate, 1);
+            let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+            assert_eq!(limiter.acquire(ip), None);
+            assert_eq!(limiter.acquire(ip), Some(MAX_RETRY_AFTER), "{rate}");
+        }
+    }
+}
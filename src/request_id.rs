@@ -0,0 +1,35 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+/// Longest incoming `X-Request-Id` that is reused rather than replaced.
+const MAX_INCOMING_LEN: usize = 128;
+
+/// Returns the ID a request is tagged with: the client's `X-Request-Id` if it
+/// is reasonable, or a new one unique within this process.
+pub(crate) fn request_id(incoming: Option<&str>) -> String {
+    if let Some(id) = incoming {
+        if !id.is_empty()
+            && id.len() <= MAX_INCOMING_LEN
+            && id.bytes().all(|b| b.is_ascii_graphic())
+        {
+            return id.to_string();
+        }
+    }
+    return generate();
+}
+
+/// Generates an ID from a random per-process prefix and a counter, so IDs
+/// never repeat within a process and rarely collide across processes.
+fn generate() -> String {
+    static PREFIX: OnceLock<u64> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let prefix = *PREFIX.get_or_init(|| RandomState::new().build_hasher().finish());
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    return format!("{prefix:016x}{n:08x}");
+}
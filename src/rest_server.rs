@@ -1,232 +1,3596 @@
 use std::{
     any::Any,
-    boxed::Box,
+    cell::{Cell, RefCell},
     collections::HashMap,
     fmt::{self},
     io::Error,
-    io::{BufRead, BufReader, ErrorKind, Write},
-    net::{TcpListener, TcpStream},
-    sync::OnceLock,
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::AtomicUsize,
+        mpsc::{self, RecvTimeoutError},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use json::JsonValue;
 use regex::Regex;
+#[cfg(feature = "tls")]
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use socket2::{Domain, Protocol, Socket, Type};
 
+use crate::access_log::{default_access_logger, AccessLogEntry, AccessLogger};
+use crate::base64;
+use crate::body::{read_chunked, BodyError, TrailerLimits};
+#[cfg(feature = "compression")]
+use crate::compression;
+use crate::connection::{Connection, MemoryTransport, Transport};
+use crate::cookie::{find_cookie, format_set_cookie, CookieOptions};
+use crate::date::{http_date, parse_http_date};
+use crate::error::ServerError;
+use crate::etag::{none_match_hit, weak_etag};
+use crate::metrics::{GaugeGuard, Metrics, ServerMetrics};
+use crate::middleware::{run_chain, Middleware};
+use crate::mime::{best_match, mime_for_body};
+use crate::multipart::Multipart;
+#[cfg(feature = "prometheus")]
+use crate::prometheus::{self, Histogram, PrometheusOptions};
+use crate::proxy::Proxy;
+use crate::request_id::request_id;
 use crate::rest_server;
+use crate::route_group::RouteGroup;
+use crate::router::{InsertError, RouteMatch, Router};
+use crate::security_headers::SecurityHeaders;
+use crate::shutdown::{ShutdownHandle, ShutdownState};
+use crate::state::StateMap;
+use crate::static_files::StaticDir;
+use crate::streaming::{BodyReader, BodyStream, ChunkedWriter, CountingWriter, EventSink};
+use crate::thread_pool::ThreadPool;
+#[cfg(feature = "tls")]
+use crate::tls;
+use crate::url::{encode_path, form_decode, normalize_path, percent_decode};
+#[cfg(feature = "websocket")]
+use crate::websocket::{self, SocketHandler, Upgrade, Upgraded, WebSocket};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HttpMethod {
     GET,
     POST,
+    PUT,
+    DELETE,
+    PATCH,
+    HEAD,
+    OPTIONS,
+}
+
+impl HttpMethod {
+    /// Returns the method name as it appears in the request line.
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            rest_server::HttpMethod::GET => "GET",
+            rest_server::HttpMethod::POST => "POST",
+            rest_server::HttpMethod::PUT => "PUT",
+            rest_server::HttpMethod::DELETE => "DELETE",
+            rest_server::HttpMethod::PATCH => "PATCH",
+            rest_server::HttpMethod::HEAD => "HEAD",
+            rest_server::HttpMethod::OPTIONS => "OPTIONS",
+        };
+    }
 }
 
 impl fmt::Display for HttpMethod {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            rest_server::HttpMethod::GET => "GET".to_string(),
-            rest_server::HttpMethod::POST => "POST".to_string(),
+        write!(f, "[{0}]", self.as_str())
+    }
+}
+
+/// HttpVersion is the protocol version of a request, which its response
+/// uses too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+}
+
+impl HttpVersion {
+    /// Returns the version as it appears in the request and status lines.
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
         };
-        write!(f, "[{s}]")
     }
 }
 
-pub struct HttpRequest<'a> {
+pub struct HttpRequest {
     method: HttpMethod,
-    path: &'a str,
-    headers: HashMap<String, String>,
-    body: String,
+    version: HttpVersion,
+    path: String,
+    params: HashMap<String, String>,
+    query: HashMap<String, String>,
+    /// The raw query string, without the `?`.
+    query_string: String,
+    /// Lowercased names with their values as sent, in the order the names
+    /// first appeared.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    remote_addr: SocketAddr,
+    request_id: String,
+    /// Template of the route that matched, once routing has happened.
+    matched_route: Option<String>,
+    state: Arc<StateMap>,
 }
 
-// type HandlerReturn = impl json::JsonValue;
-type HandlerFunc = fn(req: HttpRequest) -> Result<JsonValue, Error>;
+impl HttpRequest {
+    /// Returns the method of the request.
+    pub fn method(&self) -> HttpMethod {
+        return self.method;
+    }
 
-/// RestServer implements a Restful HTTP server.
-pub struct RestServer<'a> {
-    name: &'a str,
-    addr: &'a str,
-    port: u16,
-    path_handler_map: HashMap<&'a str, HandlerFunc>,
-}
+    /// Returns the protocol version of the request.
+    pub fn version(&self) -> HttpVersion {
+        return self.version;
+    }
 
-const HTTP_REGEX_PATTERN: &str = r"(GET|POST|OPTION|PUT|DELETE)\s(\/[\S]*)\s([\S]+)$";
+    /// Returns the path of the request, normalized for routing: escapes are
+    /// decoded except for `%25` and `%2F`, and `//`, `.` and `..` segments
+    /// are resolved.
+    pub fn path(&self) -> &str {
+        return &self.path;
+    }
 
-fn http_regex() -> &'static Regex {
-    static HTTP_REQ_REGEX: OnceLock<Regex> = OnceLock::new();
-    return HTTP_REQ_REGEX.get_or_init(|| Regex::new(HTTP_REGEX_PATTERN).unwrap());
-}
+    /// Returns the body of the request as sent, which may be binary.
+    pub fn body(&self) -> &[u8] {
+        return &self.body;
+    }
 
-impl<'a> RestServer<'a> {
-    /// Create a new RestServer
-    pub fn new(name: &'a str, addr: &'a str, port: u16) -> Result<Self, Error> {
-        if name == "" {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                "RestServer: cannot create a new server with empty name",
+    /// Returns the body of the request as text. Fails with
+    /// `ServerError::BadRequest` if it is not valid UTF-8.
+    pub fn text(&self) -> Result<&str, ServerError> {
+        return std::str::from_utf8(&self.body).map_err(|_| {
+            ServerError::BadRequest("HTTP request body is not valid UTF-8".to_string())
+        });
+    }
+
+    /// Returns the ID this request is tagged with: the client's
+    /// `X-Request-Id` header if it sent a usable one, or a newly generated
+    /// ID. It is echoed back in the response's `X-Request-Id` header.
+    pub fn request_id(&self) -> &str {
+        return &self.request_id;
+    }
+
+    /// Returns the address of the connected client. If it could not be
+    /// determined, this is the unspecified address `0.0.0.0:0`.
+    pub fn remote_addr(&self) -> SocketAddr {
+        return self.remote_addr;
+    }
+
+    /// Returns the template of the route that matched the request, such as
+    /// `/users/:id` for `/users/42`. It is `None` before routing, i.e. in
+    /// middleware added with `RestServer::use_middleware`, and in the not
+    /// found handler. Group middleware runs after routing and sees it.
+    pub fn matched_route(&self) -> Option<&str> {
+        return self.matched_route.as_deref();
+    }
+
+    /// Returns the raw query string, without the `?`, or an empty string if
+    /// the request has none.
+    pub fn query_string(&self) -> &str {
+        return &self.query_string;
+    }
+
+    /// Returns the application state of type `T` registered with
+    /// `RestServer::add_state`, or `None` if none was.
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        return self.state.get::<T>();
+    }
+
+    /// Deserializes the JSON body of the request. Fails with
+    /// `ServerError::BadRequest` if the `Content-Type` is not
+    /// `application/json` or the body is malformed.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, ServerError> {
+        let is_json = self.header("content-type").is_some_and(|v| {
+            v.split(';')
+                .next()
+                .is_some_and(|m| m.trim().eq_ignore_ascii_case("application/json"))
+        });
+        if !is_json {
+            return Err(ServerError::BadRequest(
+                "HTTP request body is not JSON: expected Content-Type application/json".to_string(),
             ));
         }
-        return Ok(RestServer {
-            name,
-            addr,
-            port,
-            path_handler_map: HashMap::new(),
+        return serde_json::from_slice(&self.body).map_err(|err| {
+            ServerError::BadRequest(format!("HTTP request body is invalid JSON: {err}"))
         });
     }
 
-    /// Adds a handler to the specified path
-    pub fn register_path(&mut self, path: &'a str, func: HandlerFunc) -> Result<(), Error> {
-        if self.path_handler_map.contains_key(path) {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "HttpServer [{0}] path [{path}]: attempted to set handler twice",
-                    self.name
-                ),
+    /// Parses an `application/x-www-form-urlencoded` body into decoded
+    /// key/value pairs. For a repeated key the last value wins; use
+    /// `form_pairs` to see every value. Fails with `ServerError::BadRequest`
+    /// if the body has another content type.
+    pub fn form(&self) -> Result<HashMap<String, String>, ServerError> {
+        return Ok(self.form_pairs()?.into_iter().collect());
+    }
+
+    /// Like `form`, but returns every pair in the order it was sent,
+    /// including repeated keys such as `tag=a&tag=b`.
+    pub fn form_pairs(&self) -> Result<Vec<(String, String)>, ServerError> {
+        return Ok(parse_urlencoded(self.form_body()?));
+    }
+
+    /// Deserializes an `application/x-www-form-urlencoded` body into `T`.
+    /// Fails with `ServerError::BadRequest` if the body has another content
+    /// type, or a field of `T` is missing or has the wrong type.
+    #[cfg(feature = "serde")]
+    pub fn form_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, ServerError> {
+        return serde_urlencoded::from_str(self.form_body()?).map_err(|err| {
+            ServerError::BadRequest(format!("HTTP request body is an invalid form: {err}"))
+        });
+    }
+
+    /// Returns the body of a form request, checking its content type.
+    fn form_body(&self) -> Result<&str, ServerError> {
+        let is_form = self.header("content-type").is_some_and(|v| {
+            v.split(';').next().is_some_and(|m| {
+                m.trim()
+                    .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+            })
+        });
+        if !is_form {
+            return Err(ServerError::BadRequest(
+                "HTTP request body is not a form: expected Content-Type application/x-www-form-urlencoded"
+                    .to_string(),
             ));
         }
-        let _ = self.path_handler_map.insert(path, func);
-        return Ok(());
+        return self.text();
     }
 
-    pub fn listen(&self) -> Result<(), Error> {
-        let port_str = self.port.to_string();
-        let full_addr = self.addr.to_owned() + ":" + &port_str;
+    /// Returns an iterator over the parts of a `multipart/form-data` body,
+    /// such as file uploads. Fails with `ServerError::BadRequest` if the body
+    /// has another content type or no boundary.
+    pub fn multipart(&self) -> Result<Multipart<'_>, ServerError> {
+        return Multipart::new(self.header("content-type"), &self.body);
+    }
 
-        // Start the listener
-        let listener = TcpListener::bind(full_addr)?;
+    /// Returns the value of the named path parameter, if present. For a route
+    /// registered as `/users/:id`, `param("id")` returns the matched segment,
+    /// percent-decoded.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        return self.params.get(name).map(|v| v.as_str());
+    }
 
-        // Listen for packets
-        for stream_result in listener.incoming() {
-            // If detect packet, read the entire request
-            match stream_result {
-                Ok(stream) => match self.handle_connection(stream) {
-                    Ok(()) => continue,
-                    Err(err) => println!("Error in handling connection: {err}"),
-                },
-                Err(err) => {
-                    println!("Error in connection: {err}");
-                }
-            };
+    /// Returns the value of the named query string parameter, if present.
+    pub fn query(&self, key: &str) -> Option<&str> {
+        return self.query.get(key).map(|v| v.as_str());
+    }
+
+    /// Deserializes the query string into `T`. Fails with
+    /// `ServerError::BadRequest` if a field of `T` is missing or has the
+    /// wrong type:
+    ///
+    /// ```
+    /// use rustful::rest_server::{HttpRequest, RestServer};
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct Page {
+    ///     q: String,
+    ///     page: Option<u32>,
+    /// }
+    ///
+    /// let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    /// svr.register_path("/search", |req: HttpRequest| {
+    ///     let page: Page = req.query_as()?;
+    ///     return Ok(format!("{0} page {1}", page.q, page.page.unwrap_or(1)));
+    /// })
+    /// .unwrap();
+    /// let resp = svr.handle_for_test(b"GET /search?q=rust&page=2 HTTP/1.1\r\n\r\nGET /search?page=2 HTTP/1.1\r\nConnection: close\r\n\r\n");
+    /// let resp = String::from_utf8(resp).unwrap();
+    /// assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+    /// assert!(resp.contains("rust page 2"));
+    /// assert!(resp.contains("HTTP/1.1 400 Bad Request\r\n"));
+    /// assert!(resp.ends_with("missing field `q`"));
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn query_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, ServerError> {
+        return serde_urlencoded::from_str(&self.query_string).map_err(|err| {
+            ServerError::BadRequest(format!("HTTP request has an invalid query string: {err}"))
+        });
+    }
+
+    /// Returns the value of the named header, if present. The lookup is
+    /// case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return find_header(&self.headers, name);
+    }
+
+    /// Returns every header of the request in the order the client sent
+    /// them, with lowercased names. A header sent more than once appears
+    /// once, where it was first sent, with its values joined by `, `.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        return self.headers.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+    }
+
+    /// Returns true if the client accepts responses of the given MIME type,
+    /// according to its `Accept` header. Without one, anything is accepted.
+    pub fn accepts(&self, mime: &str) -> bool {
+        return self.preferred_type(&[mime]).is_some();
+    }
+
+    /// Returns the type from `offered` the client prefers according to its
+    /// `Accept` header, or `None` if it accepts none of them. See
+    /// `mime::best_match`. Without an `Accept` header the first is chosen.
+    ///
+    /// ```
+    /// use rustful::error::ServerError;
+    /// use rustful::rest_server::{HttpRequest, HttpResponse};
+    ///
+    /// fn user(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    ///     if req.preferred_type(&["application/json", "text/html"]) == Some("text/html") {
+    ///         return Ok(HttpResponse::ok("<h1>Ferris</h1>"));
+    ///     }
+    ///     return Ok(HttpResponse::ok(r#"{"name":"Ferris"}"#));
+    /// }
+    /// ```
+    pub fn preferred_type<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        let accept = match self.header("accept") {
+            Some(v) if !v.trim().is_empty() => v,
+            _ => "*/*",
+        };
+        return best_match(accept, offered);
+    }
+
+    /// Returns the username and password of a `Basic` `Authorization`
+    /// header, or `None` if it is missing or malformed.
+    pub fn basic_auth(&self) -> Option<(String, String)> {
+        let encoded = auth_credentials(self.header("authorization")?, "Basic")?;
+        let decoded = String::from_utf8(base64::decode(encoded)?).ok()?;
+        let (user, password) = decoded.split_once(':')?;
+        return Some((user.to_string(), password.to_string()));
+    }
+
+    /// Returns the token of a `Bearer` `Authorization` header, or `None` if
+    /// it is missing or malformed.
+    pub fn bearer_token(&self) -> Option<&str> {
+        return auth_credentials(self.header("authorization")?, "Bearer");
+    }
+
+    /// Returns the value of the named cookie from the `Cookie` header, if
+    /// present. Cookie names are case-sensitive.
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        return find_cookie(self.header("cookie")?, name);
+    }
+}
+
+/// HttpResponse is what a handler sends back to the client.
+pub struct HttpResponse {
+    status: u16,
+    /// Names as set and values, in the order they were first set.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    /// Formatted `Set-Cookie` values, each sent as its own header line.
+    cookies: Vec<String>,
+    body_stream: Option<BodyStream>,
+    /// Takes over the connection after a `101 Switching Protocols` head.
+    #[cfg(feature = "websocket")]
+    upgrade: Option<Upgrade>,
+    /// The body as sent on the wire, if it was transformed (e.g. gzipped).
+    encoded_body: Option<Vec<u8>>,
+    /// Protocol version of the status line, that of the request.
+    version: HttpVersion,
+    /// Send only the head, as for a HEAD request. The headers still describe
+    /// the body that would have been sent.
+    omit_body: bool,
+    /// `Content-Length` of a body that is not sent, such as the upstream's
+    /// for a proxied HEAD request.
+    head_length: Option<usize>,
+    /// A handler's error, which middleware has turned into a response to add
+    /// its headers to, or an invalid header set on the response. The server
+    /// still answers it as it answers errors.
+    error: Option<ServerError>,
+}
+
+impl HttpResponse {
+    /// Creates a response with the given status code and body, which may be
+    /// text or bytes.
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        return HttpResponse {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            cookies: Vec::new(),
+            body_stream: None,
+            #[cfg(feature = "websocket")]
+            upgrade: None,
+            encoded_body: None,
+            version: HttpVersion::Http11,
+            omit_body: false,
+            head_length: None,
+            error: None,
+        };
+    }
+
+    /// Wraps a handler's error so that middleware can add headers to the
+    /// response it gets.
+    pub(crate) fn from_error(err: ServerError) -> Self {
+        let status = match err {
+            ServerError::BadRequest(_) => 400,
+            _ => 500,
+        };
+        let mut resp = HttpResponse::new(status, "");
+        resp.error = Some(err);
+        return resp;
+    }
+
+    /// Creates a `200 OK` response whose body is produced by `f` while the
+    /// response is being sent, using `Transfer-Encoding: chunked`. Each write
+    /// to the writer becomes one chunk; call `flush` to push data to the
+    /// client straight away. This avoids holding a large body in memory.
+    /// HTTP/1.0 clients get the body unframed, ended by closing the
+    /// connection.
+    pub fn stream<F>(f: F) -> Self
+    where
+        F: FnOnce(&mut dyn Write) -> Result<(), Error> + Send + 'static,
+    {
+        let mut resp = HttpResponse::ok("");
+        resp.body_stream = Some(Box::new(f));
+        return resp;
+    }
+
+    /// Creates a `200 OK` Server-Sent Events response. `f` runs while the
+    /// response is being sent and emits events through the sink until it
+    /// returns, which ends the stream:
+    ///
+    /// ```
+    /// use rustful::rest_server::HttpResponse;
+    ///
+    /// let resp = HttpResponse::event_stream(|sink| {
+    ///     for i in 0..3 {
+    ///         sink.send_event("tick", &i.to_string())?;
+    ///     }
+    ///     return Ok(());
+    /// });
+    /// assert_eq!(resp.header("content-type"), Some("text/event-stream"));
+    /// ```
+    ///
+    /// The stream occupies a worker thread for as long as it runs.
+    pub fn event_stream<F>(f: F) -> Self
+    where
+        F: FnOnce(&mut EventSink) -> Result<(), Error> + Send + 'static,
+    {
+        let mut resp = HttpResponse::stream(move |writer| f(&mut EventSink::new(writer)));
+        resp.set_header("Content-Type", "text/event-stream");
+        resp.set_header("Cache-Control", "no-cache");
+        return resp;
+    }
+
+    /// Creates a `200 OK` response with the given body.
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        return HttpResponse::new(200, body);
+    }
+
+    /// Creates a `302 Found` response sending the client to `location`.
+    pub fn redirect(location: &str) -> Self {
+        let mut resp = HttpResponse::new(302, "");
+        resp.set_header("Location", location);
+        return resp;
+    }
+
+    /// Creates a `301 Moved Permanently` response sending the client to
+    /// `location`. Clients and caches may remember it.
+    pub fn redirect_permanent(location: &str) -> Self {
+        let mut resp = HttpResponse::new(301, "");
+        resp.set_header("Location", location);
+        return resp;
+    }
+
+    /// Creates a redirect with another status, such as `303 See Other` to
+    /// answer a form post with a page to GET, or `307 Temporary Redirect` and
+    /// `308 Permanent Redirect`, which make the client repeat the method and
+    /// body.
+    pub fn redirect_with_status(status: u16, location: &str) -> Self {
+        let mut resp = HttpResponse::new(status, "");
+        resp.set_header("Location", location);
+        return resp;
+    }
+
+    /// Creates a `200 OK` response with the value serialized as JSON and
+    /// `Content-Type: application/json`.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize>(value: T) -> Result<Self, ServerError> {
+        let body = serde_json::to_string(&value).map_err(|err| {
+            ServerError::HandlerError(format!("cannot serialize JSON response: {err}"))
+        })?;
+        let mut resp = HttpResponse::ok(body);
+        resp.set_header("Content-Type", "application/json");
+        return Ok(resp);
+    }
+
+    /// Returns the status code of the response.
+    pub fn status(&self) -> u16 {
+        return self.status;
+    }
+
+    /// Returns the value of the named header, if set. The lookup is
+    /// case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return find_header(&self.headers, name);
+    }
+
+    /// Returns the headers set on the response in the order they were first
+    /// set, followed by a `Set-Cookie` header per cookie. Headers the
+    /// server adds when sending the response, such as `Content-Length` and
+    /// `Date`, are not included.
+    ///
+    /// ```
+    /// use rustful::rest_server::HttpResponse;
+    ///
+    /// let mut resp = HttpResponse::ok("hi");
+    /// resp.set_header("X-B", "1");
+    /// resp.set_header("X-A", "2");
+    /// resp.set_header("x-b", "3");
+    /// let headers: Vec<_> = resp.headers().collect();
+    /// assert_eq!(headers, [("x-b", "3"), ("X-A", "2")]);
+    /// ```
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        let cookies = self.cookies.iter().map(|c| ("Set-Cookie", c.as_str()));
+        return self
+            .headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .chain(cookies);
+    }
+
+    /// Sets a header on the response, replacing any previous value. A
+    /// replaced header keeps its place in the order headers are sent.
+    ///
+    /// A header whose name is not valid or whose value contains a CR, LF or
+    /// NUL would let the value add headers of its own, so it is not set, and
+    /// the server answers the request as if the handler had failed with
+    /// `ServerError::HandlerError`. Check untrusted input, such as a
+    /// redirect target taken from the query, with `is_valid_header` first.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        if !is_valid_header(name, value) {
+            if self.error.is_none() {
+                self.error = Some(ServerError::HandlerError(format!(
+                    "invalid response header {name:?}: {value:?}"
+                )));
+            }
+            return;
+        }
+        match self
+            .headers
+            .iter()
+            .position(|(k, _)| k.eq_ignore_ascii_case(name))
+        {
+            Some(i) => {
+                self.headers[i] = (name.to_string(), value.to_string());
+            }
+            None => self.headers.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Sets the `ETag` header. `etag` is a quoted tag such as `"v2"` or
+    /// `W/"v2"`; an unquoted one is quoted. GET and HEAD requests whose
+    /// `If-None-Match` lists the tag are answered with `304 Not Modified`.
+    pub fn set_etag(&mut self, etag: &str) {
+        if etag.ends_with('"') {
+            self.set_header("ETag", etag);
+        } else {
+            self.set_header("ETag", &format!("\"{etag}\""));
         }
+    }
+
+    /// Sets a weak `ETag` computed from the body. See `etag::weak_etag`.
+    pub fn set_weak_etag(&mut self) {
+        let etag = weak_etag(&self.body);
+        self.set_header("ETag", &etag);
+    }
 
-        let _ = listener.accept()?;
+    /// Adds a header name to `Vary`, keeping the names already listed.
+    pub(crate) fn add_vary(&mut self, name: &str) {
+        let vary = match self.header("vary") {
+            Some(v) if v.split(',').any(|n| n.trim().eq_ignore_ascii_case(name)) => return,
+            Some(v) => format!("{v}, {name}"),
+            None => name.to_string(),
+        };
+        self.set_header("Vary", &vary);
+    }
+
+    /// Adds a `Set-Cookie` header with the given options. Each call adds
+    /// another cookie. Fails with `ServerError::HandlerError` if the name,
+    /// value or path contains characters a cookie cannot carry.
+    pub fn set_cookie(
+        &mut self,
+        name: &str,
+        value: &str,
+        options: &CookieOptions,
+    ) -> Result<(), ServerError> {
+        self.cookies.push(format_set_cookie(name, value, options)?);
         return Ok(());
     }
 
-    fn handle_connection(&self, mut stream: TcpStream) -> Result<(), Error> {
-        println!("Connection established!\nRequest: {:?}", stream);
+    /// Adds a `Set-Cookie` header with an already formatted value.
+    pub(crate) fn add_raw_cookie(&mut self, cookie: &str) {
+        self.cookies.push(cookie.to_string());
+    }
 
-        // handle_request(self, &mut stream);
+    /// Returns the body of the response.
+    pub fn body(&self) -> &[u8] {
+        return &self.body;
+    }
 
-        let buf_reader = BufReader::new(&stream);
-        let http_request: Vec<_> = buf_reader
-            .lines()
-            .map(|result| {
-                let str = result.unwrap();
-                println!("- Request line: {str}");
-                return str;
-            })
-            .take_while(|line| !line.is_empty())
-            .collect();
+    /// Returns the body of the response as text, or `None` if it is not
+    /// valid UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        return std::str::from_utf8(&self.body).ok();
+    }
 
-        println!("Request received:\n{:?}", http_request);
+    /// Replaces the status code, keeping the headers and body.
+    pub(crate) fn set_status(&mut self, status: u16) {
+        self.status = status;
+    }
 
-        // Parse the request to get method, path etc.
-        if http_request.len() < 1 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "HTTP request is invalid",
-            ));
+    /// Sets what takes over the connection once the head of this response,
+    /// a `101 Switching Protocols`, has been sent.
+    #[cfg(feature = "websocket")]
+    pub(crate) fn set_upgrade(&mut self, upgrade: Upgrade) {
+        self.upgrade = Some(upgrade);
+    }
+
+    /// Announces a `Content-Length` of `len` instead of the body's length,
+    /// for a response to a HEAD request whose body is not at hand.
+    pub(crate) fn set_head_length(&mut self, len: usize) {
+        self.head_length = Some(len);
+    }
+
+    /// Replaces the bytes sent for the body, keeping `body()` as it was.
+    #[cfg(feature = "compression")]
+    pub(crate) fn set_encoded_body(&mut self, bytes: Vec<u8>) {
+        self.encoded_body = Some(bytes);
+    }
+
+    /// Returns the body bytes as they are sent on the wire.
+    fn wire_body(&self) -> &[u8] {
+        return match &self.encoded_body {
+            Some(bytes) => bytes,
+            None => &self.body,
+        };
+    }
+
+    /// Serializes the response into the bytes sent on the wire: the status
+    /// line, headers and body. `Content-Length` is always computed from the
+    /// body. `Date`, and `Content-Type` for a non-empty body, are added
+    /// unless they were set. For a streaming response only the head is
+    /// returned, since the body does not exist until it is sent.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = self.serialize_head().into_bytes();
+        if self.body_stream.is_none() && !self.omit_body {
+            bytes.extend_from_slice(self.wire_body());
         }
+        return bytes;
+    }
 
-        let re = http_regex();
-        let http_captures: regex::Captures<'_>;
-        match re.captures(&http_request[0]) {
-            Some(cs) => http_captures = cs,
-            None => {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!(
-                        "HTTP request is invalid - no parts found in line: {0}",
-                        http_request[0]
-                    ),
-                ))
+    /// Serializes the status line and headers, including the blank line that
+    /// ends them.
+    fn serialize_head(&self) -> String {
+        let mut response = format!(
+            "{0} {1} {2}\r\n",
+            self.version.as_str(),
+            self.status,
+            reason_phrase(self.status)
+        );
+        for (name, value) in &self.headers {
+            if name.eq_ignore_ascii_case("content-length")
+                || name.eq_ignore_ascii_case("transfer-encoding")
+            {
+                continue;
             }
+            response.push_str(&format!("{name}: {value}\r\n"));
         }
-        println!("Parsed caputures: {:?}", http_captures);
-        if http_captures.len() != 4 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "HTTP request is invalid: expected 4 parts but found {:?}: {:#?}",
-                    http_captures.len(),
-                    http_captures,
-                ),
-            ));
+        for cookie in &self.cookies {
+            response.push_str(&format!("Set-Cookie: {cookie}\r\n"));
         }
-        let method = match &http_captures[1] {
-            "GET" => HttpMethod::GET,
-            "POST" => HttpMethod::POST,
-            s => {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!("HTTP request is invalid: unexpected method: {s}"),
-                ))
+        if self.header("content-type").is_none() && !self.body.is_empty() {
+            response.push_str(&format!("Content-Type: {0}\r\n", mime_for_body(&self.body)));
+        }
+        if self.header("date").is_none() {
+            response.push_str(&format!("Date: {0}\r\n", http_date(SystemTime::now())));
+        }
+        if self.body_stream.is_some() {
+            // HTTP/1.0 has no chunked coding, so the body ends when the
+            // connection is closed
+            if self.version == HttpVersion::Http11 {
+                response.push_str("Transfer-Encoding: chunked\r\n");
             }
-        };
-        let path = &http_captures[2];
-        let protocol = &http_captures[3];
+        } else if !status_has_no_body(self.status) {
+            let len = self.head_length.unwrap_or(self.wire_body().len());
+            response.push_str(&format!("Content-Length: {len}\r\n"));
+        }
+        response.push_str("\r\n");
+        return response;
+    }
+}
 
-        println!("Method: {method}");
-        println!("Path: {path}");
-        println!("Protocol: {protocol}");
+/// Returns true for status codes whose responses never have a body, and so
+/// no `Content-Length`.
+fn status_has_no_body(status: u16) -> bool {
+    return matches!(status, 100..=199 | 204 | 304);
+}
 
-        if !protocol.contains("HTTP") {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                "HTTP request is invalid: expected protocol to be HTTP but got {protocol}: {:#?}",
-                http_captures
-            ),
-            ));
-        }
+/// Returns the reason phrase sent alongside a status code in the status
+/// line, as listed in the IANA HTTP status code registry. Codes the
+/// registry does not list get `Unknown`; clients go by the code alone.
+///
+/// ```
+/// use rustful::rest_server::reason_phrase;
+///
+/// assert_eq!(reason_phrase(200), "OK");
+/// assert_eq!(reason_phrase(303), "See Other");
+/// assert_eq!(reason_phrase(418), "Unknown");
+/// assert_eq!(reason_phrase(422), "Unprocessable Content");
+/// assert_eq!(reason_phrase(502), "Bad Gateway");
+/// ```
+pub fn reason_phrase(status: u16) -> &'static str {
+    return match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        102 => "Processing",
+        103 => "Early Hints",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        203 => "Non-Authoritative Information",
+        204 => "No Content",
+        205 => "Reset Content",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        208 => "Already Reported",
+        226 => "IM Used",
+        300 => "Multiple Choices",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        305 => "Use Proxy",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        402 => "Payment Required",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        407 => "Proxy Authentication Required",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        421 => "Misdirected Request",
+        422 => "Unprocessable Content",
+        423 => "Locked",
+        424 => "Failed Dependency",
+        425 => "Too Early",
+        426 => "Upgrade Required",
+        428 => "Precondition Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        451 => "Unavailable For Legal Reasons",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        506 => "Variant Also Negotiates",
+        507 => "Insufficient Storage",
+        508 => "Loop Detected",
+        510 => "Not Extended",
+        511 => "Network Authentication Required",
+        _ => "Unknown",
+    };
+}
 
-        let http_request: HttpRequest = HttpRequest {
-            method: method,
-            path: path,
-            headers: HashMap::new(),
-            body: "todo".to_string(),
-        };
+/// IntoResponse converts a handler's return value into an `HttpResponse`.
+pub trait IntoResponse {
+    fn into_response(self) -> HttpResponse;
+}
 
-        // Find the handler for this path
-        // Todo: split the path into the path + vars + params etc.
+impl IntoResponse for HttpResponse {
+    fn into_response(self) -> HttpResponse {
+        return self;
+    }
+}
 
-        let resp = match self.path_handler_map.get(path) {
-            Some(handler) => handler(http_request),
-            None => {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!("HTTP request is invalid: No handler found for path {path}",),
-                ))
-            }
-        };
+/// Text becomes the body of a `200 OK` response.
+impl IntoResponse for &str {
+    fn into_response(self) -> HttpResponse {
+        return HttpResponse::ok(self);
+    }
+}
+
+/// Text becomes the body of a `200 OK` response.
+impl IntoResponse for String {
+    fn into_response(self) -> HttpResponse {
+        return HttpResponse::ok(self);
+    }
+}
+
+/// Bytes become the body of a `200 OK` response.
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> HttpResponse {
+        return HttpResponse::ok(self);
+    }
+}
+
+/// A status code and body.
+impl IntoResponse for (u16, String) {
+    fn into_response(self) -> HttpResponse {
+        return HttpResponse::new(self.0, self.1);
+    }
+}
+
+/// A plain request handler function. `R` is anything that converts into a
+/// response, such as `HttpResponse`, `&str` or `(u16, String)`. Closures that
+/// capture state work too; see `RestServer::register`.
+pub type HandlerFunc<R = HttpResponse> = fn(req: HttpRequest) -> Result<R, ServerError>;
+
+/// Produces the response for a handler that returned an error. Set with
+/// `RestServer::set_error_handler`.
+pub type ErrorHandlerFunc = fn(err: ServerError) -> HttpResponse;
+
+/// A boxed handler with its return value already converted into an
+/// `HttpResponse`. It can be passed to `RestServer::register` like any other
+/// handler.
+pub type Handler = Box<dyn Fn(HttpRequest) -> Result<HttpResponse, ServerError> + Send + Sync>;
+
+/// The innermost step of a request's middleware chain, which records the
+/// template of the route it handles in the cell it is given.
+type Endpoint<'a> =
+    &'a dyn Fn(HttpRequest, &Cell<Option<String>>) -> Result<HttpResponse, ServerError>;
+
+/// What is known of the request being read on a connection, for the access
+/// log: when it arrived, from where and, once parsed, its request line and
+/// ID.
+struct Received<'r> {
+    start: Instant,
+    remote_addr: SocketAddr,
+    line: Option<(HttpMethod, &'r str, HttpVersion)>,
+    request_id: Option<&'r str>,
+}
+
+/// A handler registered with `RestServer::register_streaming`, which reads
+/// the request body itself.
+type BodyHandler =
+    Box<dyn Fn(HttpRequest, &mut BodyReader) -> Result<HttpResponse, ServerError> + Send + Sync>;
 
-        let resp_str = match resp {
-            Ok(r) => json::stringify(r),
-            Err(err) => format!("error: {err}"),
+/// The result of a handler that returns an `HttpResponse`. The `ok`,
+/// `created`, `no_content` and `bad_request` helpers build the common ones:
+///
+/// ```
+/// use rustful::rest_server::{bad_request, ok, HandlerResult, HttpRequest};
+///
+/// fn greet(req: HttpRequest) -> HandlerResult {
+///     return match req.query("name") {
+///         Some(name) => ok(format!("hello {name}")),
+///         None => bad_request("missing name"),
+///     };
+/// }
+/// ```
+pub type HandlerResult = Result<HttpResponse, ServerError>;
+
+/// Answers with `200 OK` and the given body.
+pub fn ok(body: impl Into<Vec<u8>>) -> HandlerResult {
+    return Ok(HttpResponse::ok(body));
+}
+
+/// Answers with `201 Created` and the given body.
+pub fn created(body: impl Into<Vec<u8>>) -> HandlerResult {
+    return Ok(HttpResponse::new(201, body));
+}
+
+/// Answers with `204 No Content`.
+pub fn no_content() -> HandlerResult {
+    return Ok(HttpResponse::new(204, ""));
+}
+
+/// Answers with `400 Bad Request` and the given message as the body.
+pub fn bad_request(message: impl Into<String>) -> HandlerResult {
+    return Ok(HttpResponse::new(400, message.into()));
+}
+
+/// How a request for a path with a trailing slash, such as `/users/`, is
+/// handled when no route matches it but one matches the path without it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// The paths are distinct; the request gets a 404.
+    Strict,
+    /// The request is routed as if the trailing slash were not there.
+    Strip,
+    /// The client is redirected to the path without the trailing slash,
+    /// with `301 Moved Permanently` (`308 Permanent Redirect` for methods
+    /// other than GET and HEAD).
+    Redirect,
+}
+
+/// RestServer implements a Restful HTTP server.
+pub struct RestServer {
+    name: String,
+    addrs: Vec<String>,
+    port: u16,
+    /// Handlers are shared so that a timed-out handler can keep running on
+    /// its own thread.
+    router: Router<Arc<Handler>>,
+    /// Routes whose handlers read the request body from the connection.
+    streaming_router: Router<BodyHandler>,
+    not_found_handler: Arc<Handler>,
+    handler_timeout: Option<Duration>,
+    error_handler: Option<ErrorHandlerFunc>,
+    expose_errors: bool,
+    catch_panics: bool,
+    default_headers: HashMap<String, String>,
+    trailing_slash: TrailingSlash,
+    max_body_size: usize,
+    max_request_line: usize,
+    max_headers: usize,
+    max_header_bytes: usize,
+    workers: usize,
+    max_connections: Option<usize>,
+    /// Connections accepted and not yet closed, including those waiting for
+    /// a worker.
+    open_connections: AtomicUsize,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    nodelay: bool,
+    buffer_responses: bool,
+    backlog: i32,
+    bind_attempts: u32,
+    bind_retry_delay: Duration,
+    reuse_address: bool,
+    reuse_port: bool,
+    middleware: Vec<Box<dyn Middleware>>,
+    access_logger: AccessLogger,
+    #[cfg(feature = "compression")]
+    compression_min_size: Option<usize>,
+    #[cfg(feature = "tls")]
+    tls_config: Option<Arc<ServerConfig>>,
+    state: Arc<StateMap>,
+    metrics: Arc<ServerMetrics>,
+    #[cfg(feature = "prometheus")]
+    duration_histogram: Option<Arc<Histogram>>,
+    shutdown: Arc<ShutdownState>,
+}
+
+/// RestServerBuilder configures and creates a `RestServer`.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rustful::rest_server::RestServer;
+///
+/// let svr = RestServer::builder()
+///     .name("api")
+///     .bind("0.0.0.0")
+///     .port(8080)
+///     .workers(16)
+///     .read_timeout(Some(Duration::from_secs(10)))
+///     .build()
+///     .unwrap();
+/// ```
+pub struct RestServerBuilder {
+    name: String,
+    addrs: Vec<String>,
+    port: u16,
+    workers: usize,
+    max_connections: Option<usize>,
+    max_body_size: usize,
+    max_request_line: usize,
+    max_headers: usize,
+    max_header_bytes: usize,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    nodelay: bool,
+    buffer_responses: bool,
+    backlog: i32,
+    bind_attempts: u32,
+    bind_retry_delay: Duration,
+    reuse_address: bool,
+    reuse_port: bool,
+}
+
+impl RestServerBuilder {
+    fn new() -> Self {
+        return RestServerBuilder {
+            name: String::new(),
+            addrs: Vec::new(),
+            port: 0,
+            workers: DEFAULT_WORKERS,
+            max_connections: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_request_line: DEFAULT_MAX_REQUEST_LINE,
+            max_headers: DEFAULT_MAX_HEADERS,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            read_timeout: Some(DEFAULT_IO_TIMEOUT),
+            write_timeout: Some(DEFAULT_IO_TIMEOUT),
+            keep_alive_timeout: Some(DEFAULT_KEEP_ALIVE_TIMEOUT),
+            nodelay: false,
+            buffer_responses: true,
+            backlog: DEFAULT_BACKLOG,
+            bind_attempts: 1,
+            bind_retry_delay: Duration::ZERO,
+            reuse_address: cfg!(unix),
+            reuse_port: false,
         };
+    }
 
-        println!("Response String: {:?}", resp_str);
+    /// Sets the server name, sent in the `Server` header. Required.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        return self;
+    }
 
-        // Write to the stream and close
-        let response = format!("HTTP/1.1 200 OK\r\n\r\n{resp_str}\n");
+    /// Adds an address to listen on, e.g. `127.0.0.1`, `::1` or `0.0.0.0`.
+    /// Call it more than once to listen on several addresses with the same
+    /// handlers. Defaults to `127.0.0.1` if never called.
+    pub fn bind(mut self, addr: &str) -> Self {
+        self.addrs.push(addr.to_string());
+        return self;
+    }
 
-        stream.write_all(response.as_bytes())?;
+    /// Sets the port to listen on. Defaults to 0, which lets the operating
+    /// system pick a free port; `RestServer::local_addr` tells which.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        return self;
+    }
 
-        return Ok(());
+    /// Sets the number of worker threads. See `RestServer::set_workers`.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        return self;
+    }
+
+    /// See `RestServer::set_max_connections`.
+    pub fn max_connections(mut self, max_connections: Option<usize>) -> Self {
+        self.max_connections = max_connections;
+        return self;
+    }
+
+    /// See `RestServer::set_max_body_size`.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        return self;
+    }
+
+    /// See `RestServer::set_max_headers`.
+    pub fn max_headers(mut self, max_headers: usize) -> Self {
+        self.max_headers = max_headers;
+        return self;
+    }
+
+    /// See `RestServer::set_max_request_line`.
+    pub fn max_request_line(mut self, max_request_line: usize) -> Self {
+        self.max_request_line = max_request_line;
+        return self;
+    }
+
+    /// See `RestServer::set_max_header_bytes`.
+    pub fn max_header_bytes(mut self, max_header_bytes: usize) -> Self {
+        self.max_header_bytes = max_header_bytes;
+        return self;
+    }
+
+    /// See `RestServer::set_read_timeout`.
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        return self;
+    }
+
+    /// See `RestServer::set_write_timeout`.
+    pub fn write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        return self;
+    }
+
+    /// See `RestServer::set_keep_alive_timeout`.
+    pub fn keep_alive_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.keep_alive_timeout = timeout;
+        return self;
+    }
+
+    /// See `RestServer::set_nodelay`.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        return self;
+    }
+
+    /// See `RestServer::set_buffer_responses`.
+    pub fn buffer_responses(mut self, buffer: bool) -> Self {
+        self.buffer_responses = buffer;
+        return self;
+    }
+
+    /// See `RestServer::set_backlog`.
+    pub fn backlog(mut self, backlog: i32) -> Self {
+        self.backlog = backlog;
+        return self;
+    }
+
+    /// See `RestServer::bind_with_retry`.
+    pub fn bind_retry(mut self, attempts: u32, delay: Duration) -> Self {
+        self.bind_attempts = attempts;
+        self.bind_retry_delay = delay;
+        return self;
+    }
+
+    /// See `RestServer::set_reuse_address`.
+    pub fn reuse_address(mut self, reuse: bool) -> Self {
+        self.reuse_address = reuse;
+        return self;
+    }
+
+    /// See `RestServer::set_reuse_port`.
+    pub fn reuse_port(mut self, reuse: bool) -> Self {
+        self.reuse_port = reuse;
+        return self;
+    }
+
+    /// Validates the configuration and creates the server.
+    pub fn build(self) -> Result<RestServer, ServerError> {
+        if self.name.is_empty() {
+            return Err(ServerError::InvalidConfig(
+                "RestServer: cannot create a new server with empty name".to_string(),
+            ));
+        }
+        if self.workers == 0 {
+            return Err(ServerError::InvalidConfig(format!(
+                "RestServer [{0}]: need at least one worker",
+                self.name
+            )));
+        }
+        return Ok(RestServer {
+            name: self.name,
+            addrs: if self.addrs.is_empty() {
+                vec!["127.0.0.1".to_string()]
+            } else {
+                self.addrs
+            },
+            port: self.port,
+            router: Router::new(),
+            streaming_router: Router::new(),
+            not_found_handler: Arc::new(Box::new(|_| Ok(HttpResponse::new(404, "Not Found")))),
+            handler_timeout: None,
+            error_handler: None,
+            expose_errors: true,
+            catch_panics: true,
+            default_headers: HashMap::new(),
+            trailing_slash: TrailingSlash::Strict,
+            max_body_size: self.max_body_size,
+            max_request_line: self.max_request_line,
+            max_headers: self.max_headers,
+            max_header_bytes: self.max_header_bytes,
+            workers: self.workers,
+            max_connections: self.max_connections,
+            open_connections: AtomicUsize::new(0),
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            keep_alive_timeout: self.keep_alive_timeout,
+            nodelay: self.nodelay,
+            buffer_responses: self.buffer_responses,
+            backlog: self.backlog,
+            bind_attempts: self.bind_attempts,
+            bind_retry_delay: self.bind_retry_delay,
+            reuse_address: self.reuse_address,
+            reuse_port: self.reuse_port,
+            middleware: Vec::new(),
+            access_logger: Box::new(default_access_logger),
+            #[cfg(feature = "compression")]
+            compression_min_size: None,
+            #[cfg(feature = "tls")]
+            tls_config: None,
+            state: Arc::new(StateMap::default()),
+            metrics: Arc::new(ServerMetrics::new()),
+            #[cfg(feature = "prometheus")]
+            duration_histogram: None,
+            shutdown: ShutdownState::new(),
+        });
     }
 }
 
-// Handler for /ping
-pub fn handle_ping(req: HttpRequest) -> Result<JsonValue, Error> {
-    println!("Handling ping");
-    return Ok(JsonValue::String("{\"data\": \"pong\"}".to_string()));
+/// Default upper bound on the number of request body bytes the server reads.
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Default upper bound on the length of a request line.
+const DEFAULT_MAX_REQUEST_LINE: usize = 8 * 1024;
+
+/// Default upper bound on the number of header lines in a request.
+const DEFAULT_MAX_HEADERS: usize = 100;
+
+/// Default upper bound on the size of the header lines of a request.
+const DEFAULT_MAX_HEADER_BYTES: usize = 8 * 1024;
+
+/// Default number of worker threads handling connections.
+const DEFAULT_WORKERS: usize = 4;
+
+/// Default time to wait on a socket read or write before giving up.
+const DEFAULT_IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time allowed for writing the response to a connection over the limit.
+const REJECT_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// First pause after an accept error such as running out of file
+/// descriptors. It doubles with each error in a row, up to the maximum.
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(10);
+
+/// Longest pause between accept attempts that keep failing.
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// How often an accept loop on a port shared with `SO_REUSEPORT` checks
+/// for shutdown while no connections arrive.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often an idle keep-alive connection checks for shutdown, so that
+/// `listen` does not wait out the keep-alive timeout before returning.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default length of the queue of connections waiting to be accepted.
+const DEFAULT_BACKLOG: i32 = 128;
+
+/// Default time an idle keep-alive connection waits for its next request.
+const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Matches a whole request line: `<method> SP <path> SP <protocol>`. The
+/// separators must be single spaces, the method must be letters, and the
+/// path must start with `/` and hold only visible ASCII, with no fragment.
+const HTTP_REGEX_PATTERN: &str = r"^([A-Za-z]+) (/[[:graph:]&&[^#]]*) ([[:graph:]]+)$";
+
+fn http_regex() -> &'static Regex {
+    static HTTP_REQ_REGEX: OnceLock<Regex> = OnceLock::new();
+    return HTTP_REQ_REGEX.get_or_init(|| Regex::new(HTTP_REGEX_PATTERN).unwrap());
+}
+
+impl RestServer {
+    /// Create a new RestServer
+    pub fn new(name: &str, addr: &str, port: u16) -> Result<Self, ServerError> {
+        return RestServer::builder()
+            .name(name)
+            .bind(addr)
+            .port(port)
+            .build();
+    }
+
+    /// Returns a builder for configuring a server before creating it.
+    pub fn builder() -> RestServerBuilder {
+        return RestServerBuilder::new();
+    }
+
+    /// Sets how long a handler may run before the request is answered with
+    /// `503 Service Unavailable`. With a timeout set, each handler runs on a
+    /// thread of its own, including the middleware of its route group. A
+    /// handler that times out is abandoned, not stopped: its thread keeps
+    /// running until the handler returns, and its response is discarded.
+    /// Defaults to `None`, running handlers on the worker thread without a
+    /// limit.
+    pub fn set_handler_timeout(&mut self, timeout: Option<Duration>) {
+        self.handler_timeout = timeout;
+    }
+
+    /// Sets the maximum number of body bytes read for a single request.
+    /// Larger requests are answered with `413 Content Too Large`. Defaults to
+    /// 1 MiB.
+    pub fn set_max_body_size(&mut self, max_body_size: usize) {
+        self.max_body_size = max_body_size;
+    }
+
+    /// Sets the maximum length of the request line, such as
+    /// `GET /path?query HTTP/1.1`, not counting its line break. Requests with
+    /// a longer one are answered with `414 URI Too Long`; reading stops at
+    /// the limit. Defaults to 8 KiB.
+    ///
+    /// ```
+    /// use rustful::rest_server::{self, RestServer};
+    ///
+    /// let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    /// svr.register_path("/ping", rest_server::handle_ping).unwrap();
+    /// svr.set_max_request_line(32);
+    /// let long = format!("GET /ping?{0} HTTP/1.1\r\n\r\n", "a".repeat(100));
+    /// let resp = svr.handle_for_test(long.as_bytes());
+    /// assert!(resp.starts_with(b"HTTP/1.1 414 URI Too Long\r\n"));
+    /// let resp = svr.handle_for_test(b"GET /ping?a=b HTTP/1.1\r\n\r\n");
+    /// assert!(resp.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    /// ```
+    pub fn set_max_request_line(&mut self, max_request_line: usize) {
+        self.max_request_line = max_request_line;
+    }
+
+    /// Sets the maximum number of header lines in a request. Requests with
+    /// more are answered with `431 Request Header Fields Too Large`.
+    /// Defaults to 100.
+    pub fn set_max_headers(&mut self, max_headers: usize) {
+        self.max_headers = max_headers;
+    }
+
+    /// Sets the maximum number of bytes in the header lines of a request,
+    /// not counting the request line. Requests with more are answered with
+    /// `431 Request Header Fields Too Large`. Defaults to 8 KiB.
+    pub fn set_max_header_bytes(&mut self, max_header_bytes: usize) {
+        self.max_header_bytes = max_header_bytes;
+    }
+
+    /// Sets the number of worker threads that handle connections concurrently.
+    pub fn set_workers(&mut self, workers: usize) {
+        self.workers = workers;
+    }
+
+    /// Sets the maximum number of connections open at once, counting those
+    /// waiting for a free worker. Connections beyond it are answered with
+    /// `503 Service Unavailable` and closed right away; with TLS they are
+    /// closed without an answer. Defaults to `None`, no limit.
+    pub fn set_max_connections(&mut self, max_connections: Option<usize>) {
+        self.max_connections = max_connections;
+    }
+
+    /// Sets how long to wait for the client to send data. A client that
+    /// stalls for longer receives a `408 Request Timeout`. `None` waits forever.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Sets how long to wait for the client to accept response data. `None`
+    /// waits forever.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    /// Sets how long an idle keep-alive connection is held open waiting for
+    /// the next request. `None` waits forever.
+    ///
+    /// HTTP/1.1 connections are kept open unless the client sends
+    /// `Connection: close`; HTTP/1.0 ones only if it sends
+    /// `Connection: keep-alive`. A handler can close the connection by
+    /// setting `Connection: close` on its response. The response to the last
+    /// request on a connection carries `Connection: close`:
+    ///
+    /// ```
+    /// use rustful::rest_server::RestServer;
+    ///
+    /// let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    /// svr.register_path("/", |_req| Ok("hi")).unwrap();
+    /// let resp = svr.handle_for_test(
+    ///     b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+    /// );
+    /// let resp = String::from_utf8(resp).unwrap();
+    /// let responses: Vec<&str> = resp.split("HTTP/1.1 200 OK").skip(1).collect();
+    /// assert_eq!(responses.len(), 2);
+    /// assert!(!responses[0].contains("Connection: close"));
+    /// assert!(responses[1].contains("Connection: close"));
+    ///
+    /// let resp = svr.handle_for_test(
+    ///     b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET / HTTP/1.0\r\n\r\n",
+    /// );
+    /// let resp = String::from_utf8(resp).unwrap();
+    /// let responses: Vec<&str> = resp.split("HTTP/1.0 200 OK").skip(1).collect();
+    /// assert_eq!(responses.len(), 2);
+    /// assert!(responses[0].contains("Connection: keep-alive"));
+    /// assert!(responses[1].contains("Connection: close"));
+    /// ```
+    pub fn set_keep_alive_timeout(&mut self, timeout: Option<Duration>) {
+        self.keep_alive_timeout = timeout;
+    }
+
+    /// Serves HTTPS instead of plain HTTP on every bound address, using the
+    /// PEM certificate chain and private key at the given paths. Fails with
+    /// `ServerError::InvalidConfig` if they cannot be loaded.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(&mut self, cert_path: &str, key_path: &str) -> Result<(), ServerError> {
+        self.tls_config = Some(tls::load_config(cert_path, key_path)?);
+        return Ok(());
+    }
+
+    /// Registers `GET /healthz`, which always answers `200 OK` while the
+    /// server runs, and `GET /readyz`, which answers `200 OK` while the server
+    /// is accepting connections and `503 Service Unavailable` once shutdown
+    /// has begun. Both reply with a small JSON status.
+    pub fn enable_health_endpoints(&mut self) -> Result<(), ServerError> {
+        self.add_route(
+            HttpMethod::GET,
+            "/healthz",
+            Box::new(|_| Ok(json_status(200, "ok"))),
+        )?;
+        let state = Arc::clone(&self.shutdown);
+        return self.add_route(
+            HttpMethod::GET,
+            "/readyz",
+            Box::new(move |_| {
+                if state.is_accepting() {
+                    return Ok(json_status(200, "ready"));
+                }
+                return Ok(json_status(503, "not ready"));
+            }),
+        );
+    }
+
+    /// Sets whether `TCP_NODELAY` is set on accepted connections, disabling
+    /// Nagle's algorithm so small responses are sent without delay. Defaults
+    /// to false, i.e. the operating system's behavior.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+    }
+
+    /// Sets whether responses are written through a buffer that is flushed
+    /// once per response, rather than straight to the connection. Buffering
+    /// sends the head and a small body in a single write, saving system
+    /// calls and, with `set_nodelay`, small packets. A streamed body still
+    /// reaches the client whenever the handler calls `flush`; without
+    /// buffering, each write goes out as soon as it is made, which suits
+    /// streams that write often but never flush. Defaults to true.
+    pub fn set_buffer_responses(&mut self, buffer: bool) {
+        self.buffer_responses = buffer;
+    }
+
+    /// Sets the length of the queue of connections waiting to be accepted,
+    /// used when `listen` binds. Defaults to 128. The operating system may
+    /// cap it, e.g. at `net.core.somaxconn` on Linux.
+    pub fn set_backlog(&mut self, backlog: i32) {
+        self.backlog = backlog;
+    }
+
+    /// Sets how often `listen` tries to bind an address that is in use,
+    /// waiting `delay` between attempts, before reporting it as failed. This
+    /// rides out a previous instance that still holds the port during a
+    /// rolling restart. Other bind errors are not retried. Defaults to a
+    /// single attempt; zero is treated as one.
+    pub fn bind_with_retry(&mut self, attempts: u32, delay: Duration) {
+        self.bind_attempts = attempts;
+        self.bind_retry_delay = delay;
+    }
+
+    /// Sets whether `SO_REUSEADDR` is set on listening sockets before they
+    /// are bound. Defaults to true on Unix, like `TcpListener::bind`, so a
+    /// restarted server can bind a port that still has connections in
+    /// `TIME_WAIT`. Defaults to false on Windows, where the option instead
+    /// lets another socket bind a port this one is listening on.
+    pub fn set_reuse_address(&mut self, reuse: bool) {
+        self.reuse_address = reuse;
+    }
+
+    /// Sets whether `SO_REUSEPORT` is set on listening sockets before they
+    /// are bound, so that several processes can listen on the same port. On
+    /// Linux every one of them must set it and run as the same user, and
+    /// the kernel spreads new connections across them. On macOS and the
+    /// BSDs they can share the port, but connections are not balanced.
+    /// Defaults to false. Binding fails on platforms without the option,
+    /// such as Windows, Solaris and illumos.
+    ///
+    /// Since a connection to the shared port may reach any of the sockets,
+    /// the accept loop of each also checks for shutdown every 100 ms, where
+    /// the platform lets accepting time out, as Linux does.
+    pub fn set_reuse_port(&mut self, reuse: bool) {
+        self.reuse_port = reuse;
+    }
+
+    /// Registers a value that every handler can read with
+    /// `HttpRequest::state`, such as a connection pool or configuration. One
+    /// value is kept per type; adding another of the same type replaces it.
+    /// Use atomics or a `Mutex` inside `T` for state handlers change.
+    pub fn add_state<T: Send + Sync + 'static>(&mut self, state: T) {
+        Arc::make_mut(&mut self.state).insert(state);
+    }
+
+    /// Returns a snapshot of the server's connection and request counters.
+    /// It can be called from another thread while `listen` runs.
+    pub fn metrics(&self) -> Metrics {
+        return self.metrics.snapshot();
+    }
+
+    /// Returns the address the server listens on, or `None` while `listen`
+    /// is not running. With port 0 this tells the port the operating system
+    /// picked. With several addresses it is the first; see `local_addrs`.
+    ///
+    /// ```
+    /// use std::{thread, time::Duration};
+    /// use rustful::rest_server::RestServer;
+    ///
+    /// let svr = RestServer::new("test", "127.0.0.1", 0).unwrap();
+    /// let handle = svr.shutdown_handle();
+    /// thread::scope(|scope| {
+    ///     let _ = scope.spawn(|| svr.listen());
+    ///     while svr.local_addr().is_none() {
+    ///         thread::sleep(Duration::from_millis(10));
+    ///     }
+    ///     assert_ne!(svr.local_addr().unwrap().port(), 0);
+    ///     handle.shutdown();
+    /// });
+    /// ```
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        return self.shutdown.local_addrs().first().copied();
+    }
+
+    /// Returns every address the server listens on, empty while `listen` is
+    /// not running.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        return self.shutdown.local_addrs();
+    }
+
+    /// Registers a GET endpoint, `/metrics` by default, serving the selected
+    /// metrics in the Prometheus text exposition format. Fails with
+    /// `ServerError::InvalidConfig` if the histogram buckets are not finite
+    /// and increasing.
+    #[cfg(feature = "prometheus")]
+    pub fn enable_prometheus(&mut self, options: PrometheusOptions) -> Result<(), ServerError> {
+        let mut histogram: Option<Arc<Histogram>> = None;
+        if options.duration {
+            histogram = Some(Arc::new(Histogram::new(&options.buckets)?));
+        }
+        self.duration_histogram = histogram.clone();
+        let metrics = Arc::clone(&self.metrics);
+        let path = options.path.clone();
+        return self.add_route(
+            HttpMethod::GET,
+            &path,
+            Box::new(move |_| {
+                let text = prometheus::render(&options, &metrics.snapshot(), histogram.as_deref());
+                let mut resp = HttpResponse::ok(text);
+                resp.set_header("Content-Type", "text/plain; version=0.0.4; charset=utf-8");
+                return Ok(resp);
+            }),
+        );
+    }
+
+    /// Returns a handle that can stop `listen` from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        return ShutdownHandle::new(Arc::clone(&self.shutdown));
+    }
+
+    /// Adds a GET handler to the specified path
+    pub fn register_path<R, F>(&mut self, path: &str, func: F) -> Result<(), ServerError>
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        return self.register(HttpMethod::GET, path, func);
+    }
+
+    /// Adds a handler for the specified method and path. Path segments of the
+    /// form `:name` match any value, which the handler can read with
+    /// `HttpRequest::param`. A last segment of the form `*name` matches the
+    /// rest of the path, so `/files/*path` captures `a/b.txt` from
+    /// `/files/a/b.txt`.
+    ///
+    /// The handler may be a plain `fn` or a closure capturing shared state:
+    ///
+    /// ```no_run
+    /// use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+    /// use rustful::rest_server::{HttpMethod, RestServer};
+    ///
+    /// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+    /// let hits = Arc::new(AtomicUsize::new(0));
+    /// svr.register(HttpMethod::GET, "/hits", move |_req| {
+    ///     return Ok(format!("{0}", hits.fetch_add(1, Ordering::Relaxed) + 1));
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn register<R, F>(
+        &mut self,
+        method: HttpMethod,
+        path: &str,
+        func: F,
+    ) -> Result<(), ServerError>
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        let handler: Handler = Box::new(move |req| func(req).map(IntoResponse::into_response));
+        return self.add_route(method, path, handler);
+    }
+
+    /// Adds a handler for paths matching a regular expression, which must
+    /// match the whole path. The handler reads capture groups with
+    /// `HttpRequest::param`, by index starting at `"1"`, or by name for
+    /// named groups.
+    ///
+    /// Regex routes are tried after static and `:param` routes and before
+    /// `*wildcard` routes. Among themselves, the first one registered that
+    /// matches wins.
+    ///
+    /// ```
+    /// use rustful::rest_server::{HttpMethod, RestServer};
+    ///
+    /// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+    /// svr.register_regex(HttpMethod::GET, r"/item/(\d+)(?:\.(?P<format>json|xml))?", |req| {
+    ///     let format = req.param("format").unwrap_or("html");
+    ///     return Ok(format!("item {0} as {format}", req.param("1").unwrap_or_default()));
+    /// })
+    /// .unwrap();
+    ///
+    /// let resp = svr.handle_for_test(b"GET /item/42.json HTTP/1.1\r\nConnection: close\r\n\r\n");
+    /// assert!(resp.ends_with(b"\r\n\r\nitem 42 as json"));
+    /// let resp = svr.handle_for_test(b"GET /item/abc HTTP/1.1\r\nConnection: close\r\n\r\n");
+    /// assert!(resp.starts_with(b"HTTP/1.1 404 Not Found\r\n"));
+    /// ```
+    pub fn register_regex<R, F>(
+        &mut self,
+        method: HttpMethod,
+        pattern: &str,
+        func: F,
+    ) -> Result<(), ServerError>
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        let handler: Handler = Box::new(move |req| func(req).map(IntoResponse::into_response));
+        return self
+            .router
+            .insert_regex(method, pattern, Arc::new(handler))
+            .map_err(|err| self.route_error(err, method, pattern, &format!("{method} pattern")));
+    }
+
+    /// Like `register`, but runs `middleware` around this route's handler
+    /// only, inside the middleware added with `use_middleware`. For example,
+    /// to protect one route and leave the others open:
+    ///
+    /// ```
+    /// use rustful::error::ServerError;
+    /// use rustful::middleware::Next;
+    /// use rustful::rest_server::{self, HttpMethod, HttpRequest, HttpResponse, RestServer};
+    ///
+    /// fn require_token(req: HttpRequest, next: Next) -> Result<HttpResponse, ServerError> {
+    ///     if req.header("authorization") != Some("Bearer secret") {
+    ///         return Ok(HttpResponse::new(401, "Unauthorized"));
+    ///     }
+    ///     return next(req);
+    /// }
+    ///
+    /// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+    /// svr.register_path("/ping", rest_server::handle_ping).unwrap();
+    /// svr.register_with_middleware(
+    ///     HttpMethod::GET,
+    ///     "/admin",
+    ///     vec![Box::new(require_token)],
+    ///     |_req| Ok("welcome"),
+    /// )
+    /// .unwrap();
+    ///
+    /// let resp = svr.handle_for_test(b"GET /admin HTTP/1.1\r\nConnection: close\r\n\r\n");
+    /// assert!(resp.starts_with(b"HTTP/1.1 401 Unauthorized\r\n"));
+    /// let resp = svr.handle_for_test(b"GET /ping HTTP/1.1\r\nConnection: close\r\n\r\n");
+    /// assert!(resp.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    /// ```
+    pub fn register_with_middleware<R, F>(
+        &mut self,
+        method: HttpMethod,
+        path: &str,
+        middleware: Vec<Box<dyn Middleware>>,
+        func: F,
+    ) -> Result<(), ServerError>
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        let handler: Handler = Box::new(move |req| {
+            return run_chain(&middleware, req, &|req| {
+                func(req).map(IntoResponse::into_response)
+            });
+        });
+        return self.add_route(method, path, handler);
+    }
+
+    /// Adds a handler for the specified method and path that reads the
+    /// request body from the connection as it arrives, instead of receiving
+    /// it in `HttpRequest::body`, which is left empty.
+    ///
+    /// A buffered body is held in memory in full before the handler runs, so
+    /// every request in flight may take up to the maximum body size. A
+    /// streaming handler only holds what it reads at a time, which suits
+    /// uploads that go straight to disk. The maximum body size does not
+    /// apply to it; the handler decides how much to read. Middleware added
+    /// with `use_middleware` runs as usual, but the handler timeout does
+    /// not. If the handler does not read the body to its end, the connection
+    /// is closed after the response.
+    ///
+    /// Streaming routes are matched before the others, without the
+    /// trailing slash policy, and do not show up in `Allow` headers.
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use rustful::rest_server::{HttpMethod, RestServer};
+    ///
+    /// let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    /// svr.register_streaming(HttpMethod::POST, "/upload", |_req, body| {
+    ///     let mut total = 0;
+    ///     let mut buf = [0; 4096];
+    ///     loop {
+    ///         match body.read(&mut buf)? {
+    ///             0 => break,
+    ///             n => total += n,
+    ///         }
+    ///     }
+    ///     return Ok(format!("received {total} bytes"));
+    /// })
+    /// .unwrap();
+    /// let resp = svr.handle_for_test(
+    ///     b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+    /// );
+    /// assert!(resp.ends_with(b"received 11 bytes"));
+    /// ```
+    pub fn register_streaming<R, F>(
+        &mut self,
+        method: HttpMethod,
+        path: &str,
+        func: F,
+    ) -> Result<(), ServerError>
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest, &mut BodyReader) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        let handler: BodyHandler =
+            Box::new(move |req, body| func(req, body).map(IntoResponse::into_response));
+        return self
+            .streaming_router
+            .insert(method, path, handler)
+            .map_err(|err| self.route_error(err, method, path, &format!("{method} path")));
+    }
+
+    /// Returns a group whose routes are registered under `prefix`, e.g.
+    /// `/api/v1`. Middleware added to the group only runs for its routes.
+    pub fn group(&mut self, prefix: &str) -> RouteGroup<'_> {
+        return RouteGroup::new(self, prefix);
+    }
+
+    /// Accepts WebSocket connections on `path`. The handshake is checked and
+    /// answered, then `handler` is called with the upgrade request and the
+    /// open socket; the connection closes when it returns. Messages are
+    /// limited to the maximum body size.
+    ///
+    /// Each open WebSocket occupies a worker thread for as long as it is
+    /// open, with no read timeout, so set enough workers for the expected
+    /// number of sockets.
+    ///
+    /// ```no_run
+    /// use rustful::rest_server::RestServer;
+    /// use rustful::websocket::Message;
+    ///
+    /// let mut svr = RestServer::new("chat", "127.0.0.1", 8080).unwrap();
+    /// svr.register_websocket("/echo", |_req, socket| loop {
+    ///     match socket.read_message()? {
+    ///         Message::Text(text) => socket.send_text(&text)?,
+    ///         Message::Binary(data) => socket.send_binary(&data)?,
+    ///         Message::Close(_) => return Ok(()),
+    ///         _ => {}
+    ///     }
+    /// })
+    /// .unwrap();
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub fn register_websocket<F>(&mut self, path: &str, handler: F) -> Result<(), ServerError>
+    where
+        F: Fn(HttpRequest, &mut WebSocket) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        let handler: SocketHandler = Arc::new(handler);
+        let max_message_size = self.max_body_size;
+        return self.add_route(
+            HttpMethod::GET,
+            path,
+            Box::new(move |req| Ok(websocket::handshake(req, &handler, max_message_size))),
+        );
+    }
+
+    /// Adds an already converted handler to the router.
+    pub(crate) fn add_route(
+        &mut self,
+        method: HttpMethod,
+        path: &str,
+        handler: Handler,
+    ) -> Result<(), ServerError> {
+        return self
+            .router
+            .insert(method, path, Arc::new(handler))
+            .map_err(|err| self.route_error(err, method, path, &format!("{method} path")));
+    }
+
+    /// Turns a router error for `method` and `path` into a server error.
+    /// Invalid templates are reported as `what [path]`, e.g. `GET path [/a]`.
+    fn route_error(
+        &self,
+        err: InsertError,
+        method: HttpMethod,
+        path: &str,
+        what: &str,
+    ) -> ServerError {
+        return match err {
+            InsertError::Duplicate => ServerError::DuplicateRoute {
+                method,
+                path: path.to_string(),
+            },
+            InsertError::InvalidTemplate(reason) => ServerError::InvalidConfig(format!(
+                "HttpServer [{0}] {what} [{path}]: {reason}",
+                self.name
+            )),
+        };
+    }
+
+    /// Sets the handler that answers requests no route matches, with the
+    /// full request including its path. Use it to serve a single-page app's
+    /// `index.html` for client-side routes, or to pass unknown paths on to
+    /// another server; see `examples/spa.rs`. Requests whose path has a
+    /// route for another method still get `405 Method Not Allowed`. Without
+    /// a fallback, unmatched requests get `404 Not Found`.
+    pub fn set_fallback<R, F>(&mut self, func: F)
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        self.not_found_handler = Arc::new(Box::new(move |req| {
+            func(req).map(IntoResponse::into_response)
+        }));
+    }
+
+    /// Sets the handler that answers requests no route matches. The same as
+    /// `set_fallback`.
+    pub fn set_not_found_handler<R, F>(&mut self, func: F)
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        self.set_fallback(func);
+    }
+
+    /// Sets the function that turns an error returned by a handler or
+    /// middleware into the response sent to the client, replacing the
+    /// built-in `400 Bad Request` for `ServerError::BadRequest` and
+    /// `500 Internal Server Error` for everything else.
+    pub fn set_error_handler(&mut self, func: ErrorHandlerFunc) {
+        self.error_handler = Some(func);
+    }
+
+    /// Sets how paths with a trailing slash that no route matches are
+    /// handled. Defaults to `TrailingSlash::Strict`.
+    pub fn set_trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
+    /// Sets whether the built-in error response includes the error message.
+    /// Defaults to true; turn it off in production so internal details are
+    /// only logged, never sent to clients.
+    pub fn set_expose_errors(&mut self, expose: bool) {
+        self.expose_errors = expose;
+    }
+
+    /// Sets whether a panicking handler is answered with
+    /// `500 Internal Server Error` instead of taking its worker thread down
+    /// with it. A panic elsewhere while serving a connection, such as in
+    /// middleware, closes that connection instead. The panic message is
+    /// logged. Defaults to true.
+    ///
+    /// ```
+    /// use rustful::rest_server::RestServer;
+    ///
+    /// let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    /// svr.register_path("/boom", |_req| -> Result<&str, _> { panic!("boom") })
+    ///     .unwrap();
+    /// svr.register_path("/ok", |_req| Ok("still here")).unwrap();
+    /// let resp = svr.handle_for_test(b"GET /boom HTTP/1.1\r\n\r\nGET /ok HTTP/1.1\r\n\r\n");
+    /// let resp = String::from_utf8(resp).unwrap();
+    /// assert!(resp.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    /// assert!(resp.ends_with("\r\n\r\nstill here"));
+    /// ```
+    pub fn set_catch_panics(&mut self, catch: bool) {
+        self.catch_panics = catch;
+    }
+
+    /// Sets headers added to every response, including built-in ones such as
+    /// `404 Not Found`, unless the response already has a header of the same
+    /// name. Replaces any default headers set before. Fails with
+    /// `ServerError::InvalidConfig`, keeping the previous headers, if a
+    /// header is invalid; see `is_valid_header`.
+    pub fn set_default_headers(
+        &mut self,
+        headers: HashMap<String, String>,
+    ) -> Result<(), ServerError> {
+        for (name, value) in &headers {
+            self.check_default_header(name, value)?;
+        }
+        self.default_headers = headers;
+        return Ok(());
+    }
+
+    /// Adds one header to the default headers; see `set_default_headers`.
+    pub fn set_default_header(&mut self, name: &str, value: &str) -> Result<(), ServerError> {
+        self.check_default_header(name, value)?;
+        self.default_headers
+            .retain(|k, _| !k.eq_ignore_ascii_case(name));
+        let _ = self
+            .default_headers
+            .insert(name.to_string(), value.to_string());
+        return Ok(());
+    }
+
+    fn check_default_header(&self, name: &str, value: &str) -> Result<(), ServerError> {
+        if !is_valid_header(name, value) {
+            return Err(ServerError::InvalidConfig(format!(
+                "HttpServer [{0}] invalid default header {name:?}: {value:?}",
+                self.name
+            )));
+        }
+        return Ok(());
+    }
+
+    /// Adds a middleware that runs around every request. Middlewares compose
+    /// in registration order, so the first one added sees the request first
+    /// and the response last.
+    pub fn use_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    /// Adds a `SecurityHeaders` middleware. `Strict-Transport-Security` is
+    /// sent by default if TLS has been set up with `with_tls`, so call this
+    /// after it.
+    pub fn enable_security_headers(&mut self, headers: SecurityHeaders) {
+        #[cfg(feature = "tls")]
+        let tls = self.tls_config.is_some();
+        #[cfg(not(feature = "tls"))]
+        let tls = false;
+        self.use_middleware(headers.resolve_hsts(tls));
+    }
+
+    /// Sets the function that receives one entry per handled request, after
+    /// its response has been written. The default prints a single line with
+    /// the method, path, status, body size and duration; pass `|_| {}` to
+    /// turn access logging off.
+    pub fn set_access_logger<F>(&mut self, logger: F)
+    where
+        F: Fn(&AccessLogEntry) + Send + Sync + 'static,
+    {
+        self.access_logger = Box::new(logger);
+    }
+
+    /// Gzips response bodies of at least `min_size` bytes for clients that
+    /// send `Accept-Encoding: gzip`. Content types that are already
+    /// compressed, such as images, are sent as they are.
+    #[cfg(feature = "compression")]
+    pub fn enable_compression(&mut self, min_size: usize) {
+        self.compression_min_size = Some(min_size);
+    }
+
+    /// Serves the files under `fs_root` for GET requests below `url_prefix`,
+    /// so `/static/css/site.css` maps to `<fs_root>/css/site.css` when the
+    /// prefix is `/static`. Directory requests serve their `index.html`.
+    /// Paths that try to leave `fs_root` and missing files get a 404.
+    pub fn serve_dir(&mut self, url_prefix: &str, fs_root: &str) -> Result<(), ServerError> {
+        return self.serve_dir_with_index(url_prefix, fs_root, Some("index.html"));
+    }
+
+    /// Like `serve_dir`, but with a custom index file name for directory
+    /// requests, or `None` to answer them with a 404.
+    pub fn serve_dir_with_index(
+        &mut self,
+        url_prefix: &str,
+        fs_root: &str,
+        index_file: Option<&str>,
+    ) -> Result<(), ServerError> {
+        let dir = StaticDir::new(url_prefix, fs_root, index_file);
+        let handler: Handler = Box::new(move |req| dir.serve(req));
+        let template = format!("{0}/*path", url_prefix.trim_end_matches('/'));
+        return self
+            .router
+            .insert(HttpMethod::GET, &template, Arc::new(handler))
+            .map_err(|err| self.route_error(err, HttpMethod::GET, &template, "static dir"));
+    }
+
+    /// Forwards every request below `url_prefix` to the HTTP server at
+    /// `upstream_base`, such as `http://127.0.0.1:9000/v1`, replacing the
+    /// prefix with the upstream's path, so `/api/users?page=2` is sent on as
+    /// `/v1/users?page=2` when the prefix is `/api`. The method, headers and
+    /// body are copied, except for hop-by-hop headers such as `Connection`,
+    /// and the client's address is appended to `X-Forwarded-For`. The
+    /// response is streamed back as it arrives. An upstream that cannot be
+    /// reached is answered with `502 Bad Gateway`.
+    ///
+    /// Only plain HTTP upstreams are supported; each request opens a new
+    /// connection to the upstream.
+    pub fn proxy(&mut self, url_prefix: &str, upstream_base: &str) -> Result<(), ServerError> {
+        let proxy = Arc::new(Proxy::new(&self.name, url_prefix, upstream_base)?);
+        let template = format!("{0}/*path", url_prefix.trim_end_matches('/'));
+        let methods = [
+            HttpMethod::GET,
+            HttpMethod::POST,
+            HttpMethod::PUT,
+            HttpMethod::DELETE,
+            HttpMethod::PATCH,
+            HttpMethod::HEAD,
+            HttpMethod::OPTIONS,
+        ];
+        // Checked up front so that a clash leaves no proxy routes behind
+        for method in methods {
+            self.router
+                .check_insert(method, &template)
+                .map_err(|err| self.route_error(err, method, &template, "proxy"))?;
+        }
+        for method in methods {
+            let proxy = Arc::clone(&proxy);
+            let handler: Handler = Box::new(move |req| proxy.serve(req));
+            self.router
+                .insert(method, &template, Arc::new(handler))
+                .map_err(|err| self.route_error(err, method, &template, "proxy"))?;
+        }
+        return Ok(());
+    }
+
+    /// Returns the method and path of every registered route, including
+    /// streaming routes and those added by `serve_dir`, `proxy` and route
+    /// groups. Paths are given as registered, e.g. `/users/:id`, or as the
+    /// pattern of a regex route. Each method and path appears once:
+    ///
+    /// ```
+    /// use rustful::rest_server::{HttpMethod, RestServer};
+    ///
+    /// let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    /// svr.register_path("/users", |_req| Ok("all")).unwrap();
+    /// svr.register(HttpMethod::DELETE, "/users/:id", |_req| Ok("gone"))
+    ///     .unwrap();
+    /// let routes = svr.routes();
+    /// assert!(routes.contains(&(HttpMethod::GET, "/users".to_string())));
+    /// assert!(routes.contains(&(HttpMethod::DELETE, "/users/:id".to_string())));
+    /// assert_eq!(routes.len(), 2);
+    /// ```
+    pub fn routes(&self) -> Vec<(HttpMethod, String)> {
+        let mut routes: Vec<(HttpMethod, String)> = Vec::new();
+        for (method, path) in self.router.routes().chain(self.streaming_router.routes()) {
+            if !routes.iter().any(|(m, p)| *m == method && p == path) {
+                routes.push((method, path.to_string()));
+            }
+        }
+        return routes;
+    }
+
+    /// Binds every configured address and serves connections on all of them
+    /// until shutdown. Addresses that fail to bind are reported and skipped;
+    /// it is an error only if none of them could be bound.
+    ///
+    /// The routes are fixed while the server listens. `listen` borrows the
+    /// server, and registering a route needs it mutably, so the borrow
+    /// checker rejects registration until `listen` has returned:
+    ///
+    /// ```compile_fail
+    /// use std::thread;
+    /// use rustful::rest_server::{self, RestServer};
+    ///
+    /// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+    /// thread::scope(|scope| {
+    ///     let _ = scope.spawn(|| svr.listen());
+    ///     svr.register_path("/ping", rest_server::handle_ping).unwrap();
+    /// });
+    /// ```
+    ///
+    /// Register every route before calling `listen`.
+    ///
+    /// Once `ShutdownHandle::shutdown` is called, `listen` stops accepting,
+    /// waits for the connections being handled, and returns `Ok(())`.
+    /// Without a shutdown it never returns, unless a listener fails in a way
+    /// that retrying cannot fix; then the server shuts down the same way and
+    /// returns the error.
+    ///
+    /// Errors accepting a connection that may go away, such as running out
+    /// of file descriptors, are logged and retried after a pause that grows
+    /// from 10 ms to at most 1 s while they persist.
+    pub fn listen(&self) -> Result<(), ServerError> {
+        let mut listeners: Vec<TcpListener> = Vec::new();
+        let mut last_err: Option<ServerError> = None;
+        for addr in &self.addrs {
+            // Allow bracketed IPv6 literals like `[::1]`
+            let host = addr.trim_start_matches('[').trim_end_matches(']');
+            match self.bind_retrying(host) {
+                Ok(listener) => {
+                    println!(
+                        "RestServer [{0}]: listening on {1}",
+                        self.name,
+                        listener.local_addr()?
+                    );
+                    listeners.push(listener);
+                }
+                Err(err) => {
+                    println!("RestServer [{0}]: {err}", self.name);
+                    last_err = Some(err);
+                }
+            }
+        }
+        if listeners.is_empty() {
+            return Err(last_err.unwrap_or_else(|| {
+                ServerError::InvalidConfig(format!(
+                    "RestServer [{0}]: no address to listen on",
+                    self.name
+                ))
+            }));
+        }
+        let local_addrs = listeners
+            .iter()
+            .map(|l| l.local_addr())
+            .collect::<Result<Vec<_>, Error>>()?;
+        self.shutdown.set_local_addrs(local_addrs);
+
+        // Run one accept loop per listener, all handing connections to the
+        // same worker pool. The pool is dropped once every accept loop has
+        // stopped, and leaving the outer scope waits for in-flight
+        // connections to finish.
+        let results = thread::scope(|scope| {
+            let pool = ThreadPool::new(scope, self.workers);
+            return thread::scope(|accept_scope| {
+                let loops: Vec<_> = listeners
+                    .iter()
+                    .map(|listener| {
+                        accept_scope.spawn(|| self.accept_loop(listener.incoming(), &pool))
+                    })
+                    .collect();
+                return loops
+                    .into_iter()
+                    .map(|handle| {
+                        handle
+                            .join()
+                            .unwrap_or_else(|panic| panic::resume_unwind(panic))
+                    })
+                    .collect::<Vec<_>>();
+            });
+        });
+
+        self.shutdown.set_local_addrs(Vec::new());
+        for result in results {
+            result?;
+        }
+        return Ok(());
+    }
+
+    /// Like `listen`, but also shuts down gracefully when the process is
+    /// asked to terminate: new connections are refused, the requests being
+    /// handled finish, and this returns `Ok(())`.
+    ///
+    /// On Unix the signals are `SIGINT` and `SIGTERM`; on Windows, Ctrl+C,
+    /// Ctrl+Break and closing the console window. A process can install the
+    /// handler only once, so this fails with `ServerError::InvalidConfig` if
+    /// it is called again or another signal handler was installed through
+    /// the `ctrlc` crate. Signals that arrive while draining are ignored, so
+    /// a handler that never returns keeps the process alive.
+    #[cfg(feature = "signals")]
+    pub fn listen_with_signal_handling(&self) -> Result<(), ServerError> {
+        let handle = self.shutdown_handle();
+        let name = self.name.clone();
+        ctrlc::set_handler(move || {
+            if !handle.is_shutdown() {
+                println!("RestServer [{name}]: termination signal received, shutting down");
+                handle.shutdown();
+            }
+        })
+        .map_err(|err| {
+            ServerError::InvalidConfig(format!(
+                "RestServer [{0}]: cannot install signal handler: {err}",
+                self.name
+            ))
+        })?;
+        return self.listen();
+    }
+
+    /// Accepts connections from one listener's `incoming()` until shutdown
+    /// is requested, or the listener fails for good.
+    fn accept_loop<'scope>(
+        &'scope self,
+        incoming: impl Iterator<Item = Result<TcpStream, Error>>,
+        pool: &ThreadPool<'scope>,
+    ) -> Result<(), Error> {
+        let mut backoff = ACCEPT_BACKOFF_MIN;
+        // `incoming()` never ends on its own; the loop stops when the
+        // connection `shutdown` makes to wake it finds the flag set
+        for stream_result in incoming {
+            if self.shutdown.is_requested() {
+                break;
+            }
+            // If detect packet, read the entire request
+            match stream_result {
+                Ok(stream) => {
+                    backoff = ACCEPT_BACKOFF_MIN;
+                    let slot = match self.max_connections {
+                        Some(max) => GaugeGuard::try_new(&self.open_connections, max),
+                        None => Some(GaugeGuard::new(&self.open_connections)),
+                    };
+                    let slot = match slot {
+                        Some(slot) => slot,
+                        None => {
+                            self.reject_connection(stream);
+                            continue;
+                        }
+                    };
+                    pool.execute(move || {
+                        let _slot = slot;
+                        // A panic outside a route handler, e.g. in middleware,
+                        // closes the connection but leaves the worker running
+                        let result = match self.catch_panics {
+                            true => panic::catch_unwind(AssertUnwindSafe(|| {
+                                self.handle_connection(stream)
+                            })),
+                            false => Ok(self.handle_connection(stream)),
+                        };
+                        match result {
+                            Ok(Ok(())) => {}
+                            Ok(Err(err)) => println!("Error in handling connection: {err}"),
+                            Err(payload) => println!(
+                                "RestServer [{0}]: connection closed after a panic: {1}",
+                                self.name,
+                                panic_message(&*payload)
+                            ),
+                        }
+                    });
+                }
+                // No connection within the poll interval of a shared port
+                Err(err)
+                    if self.reuse_port
+                        && matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                // The client went away before it was accepted; the next
+                // connection is unaffected
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        ErrorKind::ConnectionAborted
+                            | ErrorKind::ConnectionReset
+                            | ErrorKind::Interrupted
+                            | ErrorKind::WouldBlock
+                            | ErrorKind::TimedOut
+                    ) =>
+                {
+                    println!("Error in connection: {err}");
+                }
+                // The listener itself is unusable, e.g. no longer listening
+                Err(err)
+                    if matches!(err.kind(), ErrorKind::InvalidInput | ErrorKind::Unsupported) =>
+                {
+                    println!(
+                        "RestServer [{0}]: cannot accept connections, shutting down: {err}",
+                        self.name
+                    );
+                    self.shutdown_handle().shutdown();
+                    return Err(err);
+                }
+                // Likely out of file descriptors or memory. Retrying at once
+                // would fail the same way, so give connections time to close.
+                Err(err) => {
+                    println!(
+                        "Error in connection: {err}; retrying in {0} ms",
+                        backoff.as_millis()
+                    );
+                    thread::sleep(backoff);
+                    backoff = next_backoff(backoff);
+                }
+            };
+        }
+        return Ok(());
+    }
+
+    /// Turns away a connection over the connection limit. It is answered
+    /// from the accept loop, so the write is given little time.
+    fn reject_connection(&self, mut stream: TcpStream) {
+        // A TLS client cannot read a plain-text response
+        #[cfg(feature = "tls")]
+        if self.tls_config.is_some() {
+            return;
+        }
+        let _ = stream.set_write_timeout(Some(REJECT_WRITE_TIMEOUT));
+        let mut resp = HttpResponse::new(503, "Service Unavailable");
+        resp.set_header("Connection", "close");
+        let _ = self.write_response(&mut stream, resp);
+        let _ = stream.shutdown(Shutdown::Write);
+    }
+
+    /// Like `bind_listener`, but tries again while the address is in use,
+    /// as configured with `bind_with_retry`.
+    fn bind_retrying(&self, host: &str) -> Result<TcpListener, ServerError> {
+        let mut attempt: u32 = 1;
+        loop {
+            match self.bind_listener(host) {
+                Err(ServerError::BindFailed { addr, source })
+                    if source.kind() == ErrorKind::AddrInUse && attempt < self.bind_attempts =>
+                {
+                    println!(
+                        "RestServer [{0}]: {addr} is in use, retrying in {1} ms (attempt {attempt} of {2})",
+                        self.name,
+                        self.bind_retry_delay.as_millis(),
+                        self.bind_attempts
+                    );
+                    thread::sleep(self.bind_retry_delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Binds a listener on `host` and the configured port with the
+    /// configured backlog, trying each address the host resolves to.
+    fn bind_listener(&self, host: &str) -> Result<TcpListener, ServerError> {
+        // Shown in errors; IPv6 literals are bracketed like in URLs
+        let mut host_port = format!("{host}:{0}", self.port);
+        if host.contains(':') {
+            host_port = format!("[{host}]:{0}", self.port);
+        }
+        let addrs =
+            (host, self.port)
+                .to_socket_addrs()
+                .map_err(|source| ServerError::BindFailed {
+                    addr: host_port.clone(),
+                    source,
+                })?;
+        let mut last_err: Option<ServerError> = None;
+        for addr in addrs {
+            let bound = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))
+                .and_then(|socket| {
+                    if self.reuse_address {
+                        socket.set_reuse_address(true)?;
+                    }
+                    if self.reuse_port {
+                        set_reuse_port(&socket)?;
+                        // The connection `shutdown` makes to wake the accept
+                        // loop may reach another socket sharing the port, so
+                        // the loop also wakes up on its own now and then
+                        socket.set_read_timeout(Some(ACCEPT_POLL_INTERVAL))?;
+                    }
+                    socket.bind(&addr.into())?;
+                    socket.listen(self.backlog)?;
+                    Ok(socket)
+                });
+            match bound {
+                Ok(socket) => return Ok(socket.into()),
+                Err(source) => {
+                    last_err = Some(ServerError::BindFailed {
+                        addr: addr.to_string(),
+                        source,
+                    })
+                }
+            }
+        }
+        return Err(last_err.unwrap_or_else(|| ServerError::BindFailed {
+            addr: host_port,
+            source: Error::new(ErrorKind::NotFound, "host did not resolve to any address"),
+        }));
+    }
+
+    fn handle_connection(&self, stream: TcpStream) -> Result<(), Error> {
+        stream.set_write_timeout(self.write_timeout)?;
+        if self.nodelay {
+            stream.set_nodelay(true)?;
+        }
+        let remote_addr = stream
+            .peer_addr()
+            .unwrap_or_else(|_| SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+
+        #[cfg(feature = "tls")]
+        let conn = match &self.tls_config {
+            Some(config) => {
+                let tls = ServerConnection::new(Arc::clone(config)).map_err(Error::other)?;
+                Connection::Tls(Box::new(StreamOwned::new(tls, stream)))
+            }
+            None => Connection::Plain(stream),
+        };
+        #[cfg(not(feature = "tls"))]
+        let conn = Connection::Plain(stream);
+        return self.serve_connection(conn, remote_addr);
+    }
+
+    /// Runs raw request bytes through the same parsing, dispatch and
+    /// serialization as a real connection, without a socket, and returns the
+    /// raw response bytes. Several pipelined requests produce several
+    /// responses. Meant for tests:
+    ///
+    /// ```
+    /// use rustful::rest_server::{self, RestServer};
+    ///
+    /// let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    /// svr.register_path("/ping", rest_server::handle_ping).unwrap();
+    /// let resp = svr.handle_for_test(b"GET /ping HTTP/1.1\r\nConnection: close\r\n\r\n");
+    /// assert!(resp.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    /// assert!(resp.ends_with(b"\r\n\r\npong"));
+    /// ```
+    ///
+    /// Pipelined requests are answered in the order they were sent:
+    ///
+    /// ```
+    /// use rustful::rest_server::{HttpMethod, RestServer};
+    ///
+    /// let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    /// svr.register_path("/a", |_req| Ok("first")).unwrap();
+    /// svr.register(HttpMethod::POST, "/b", |req| Ok(format!("second {0}", req.text()?)))
+    ///     .unwrap();
+    /// let resp = svr.handle_for_test(
+    ///     b"GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nhiGET /nope HTTP/1.1\r\nConnection: close\r\n\r\n",
+    /// );
+    /// let resp = String::from_utf8(resp).unwrap();
+    /// let statuses: Vec<&str> = resp.split("HTTP/1.1 ").skip(1).map(|r| &r[..3]).collect();
+    /// assert_eq!(statuses, ["200", "200", "404"]);
+    /// assert!(resp.find("first").unwrap() < resp.find("second hi").unwrap());
+    /// ```
+    pub fn handle_for_test(&self, raw_request: &[u8]) -> Vec<u8> {
+        let mut conn = MemoryTransport::new(raw_request);
+        let remote_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+        if let Err(err) = self.serve_connection(&mut conn, remote_addr) {
+            println!("Error in handling connection: {err}");
+        }
+        return conn.into_output();
+    }
+
+    /// Serves requests from `conn` until the client or server ends the
+    /// connection. Works over any transport, not only sockets.
+    fn serve_connection<S: Transport>(
+        &self,
+        conn: S,
+        remote_addr: SocketAddr,
+    ) -> Result<(), Error> {
+        let _active = self.metrics.open_connection();
+        // The reader lives as long as the connection so that bytes it has
+        // buffered past one request are kept for the next.
+        let mut buf_reader = BufReader::new(conn);
+        let mut first = true;
+        loop {
+            if !first {
+                // Wait for the client's next request, closing the connection
+                // if it hangs up, stays idle too long, or we are shutting down
+                let idle_since = Instant::now();
+                loop {
+                    if self.shutdown.is_requested() {
+                        return Ok(());
+                    }
+                    let mut wait = IDLE_POLL_INTERVAL;
+                    if let Some(timeout) = self.keep_alive_timeout {
+                        let left = timeout.saturating_sub(idle_since.elapsed());
+                        if left.is_zero() {
+                            return Ok(());
+                        }
+                        wait = wait.min(left);
+                    }
+                    buf_reader.get_ref().set_read_timeout(Some(wait))?;
+                    match buf_reader.fill_buf() {
+                        Ok([]) => return Ok(()),
+                        Ok(_) => break,
+                        Err(err) if is_timeout(&err) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                if self.shutdown.is_requested() {
+                    return Ok(());
+                }
+            }
+            first = false;
+
+            buf_reader.get_ref().set_read_timeout(self.read_timeout)?;
+            if !self.handle_request(&mut buf_reader, remote_addr)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads one request from the connection, dispatches it and writes the
+    /// response. Returns whether the connection should be kept open for
+    /// another request.
+    fn handle_request<S: Transport>(
+        &self,
+        buf_reader: &mut BufReader<S>,
+        remote_addr: SocketAddr,
+    ) -> Result<bool, Error> {
+        let start = Instant::now();
+        let mut received = Received {
+            start,
+            remote_addr,
+            line: None,
+            request_id: None,
+        };
+        let mut http_request: Vec<String> = Vec::new();
+        let mut header_bytes: usize = 0;
+        let mut skipped_empty_line = false;
+        loop {
+            let mut str = String::new();
+            // Read the request line and header lines no further than their
+            // size limits, with room for the CRLF that ends them
+            let mut limit = self.max_request_line.saturating_add(2) as u64;
+            if !http_request.is_empty() {
+                limit = (self.max_header_bytes.saturating_sub(header_bytes) + 2) as u64;
+            }
+            match buf_reader.by_ref().take(limit).read_line(&mut str) {
+                // The client hung up. Port scanners and health probes often
+                // connect and close without sending a whole request line,
+                // which is not worth a response or a log line.
+                Ok(n) if !str.ends_with('\n') && http_request.is_empty() && (n as u64) < limit => {
+                    return Ok(false)
+                }
+                // The headers were cut off before the empty line that ends
+                // them, so the request is incomplete and is not handled
+                Ok(n) if !str.ends_with('\n') && (n as u64) < limit => {
+                    return self.bad_request(
+                        buf_reader.get_mut(),
+                        &received,
+                        "headers ended early",
+                    );
+                }
+                Ok(_) => {}
+                Err(err) if is_timeout(&err) => {
+                    return self.respond_and_close(
+                        buf_reader.get_mut(),
+                        &received,
+                        HttpResponse::new(408, "Request Timeout"),
+                    );
+                }
+                // The line was not valid UTF-8. The client is still there, so
+                // tell it what went wrong rather than dropping the connection.
+                Err(err) if err.kind() == ErrorKind::InvalidData => {
+                    let resp = HttpResponse::new(400, "Bad Request: request is not valid UTF-8");
+                    return self.respond_and_close(buf_reader.get_mut(), &received, resp);
+                }
+                Err(err) => return Err(err),
+            };
+            let line = str.strip_suffix('\n').unwrap_or(&str);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            // Some clients send a stray CRLF after a request body, so one
+            // empty line before the request line is ignored
+            if line.is_empty() && http_request.is_empty() && !skipped_empty_line {
+                skipped_empty_line = true;
+                continue;
+            }
+            if line.is_empty() {
+                break;
+            }
+            if http_request.is_empty() && line.len() > self.max_request_line {
+                let resp = HttpResponse::new(414, "URI Too Long");
+                return self.respond_and_close(buf_reader.get_mut(), &received, resp);
+            }
+            if !http_request.is_empty() {
+                header_bytes += str.len();
+                if header_bytes > self.max_header_bytes || http_request.len() > self.max_headers {
+                    let resp = HttpResponse::new(431, "Request Header Fields Too Large");
+                    return self.respond_and_close(buf_reader.get_mut(), &received, resp);
+                }
+            }
+            http_request.push(line.to_string());
+        }
+
+        // Parse the request to get method, path etc. Problems with the
+        // request itself are answered with a 400; only I/O errors on the
+        // connection are returned as errors.
+        if http_request.is_empty() {
+            return self.bad_request(buf_reader.get_mut(), &received, "empty request");
+        }
+
+        let re = http_regex();
+        let http_captures = match re.captures(&http_request[0]) {
+            Some(cs) => cs,
+            None => {
+                return self.bad_request(buf_reader.get_mut(), &received, "malformed request line")
+            }
+        };
+        let method = match http_captures[1].to_ascii_uppercase().as_str() {
+            "GET" => HttpMethod::GET,
+            "POST" => HttpMethod::POST,
+            "PUT" => HttpMethod::PUT,
+            "DELETE" => HttpMethod::DELETE,
+            "PATCH" => HttpMethod::PATCH,
+            "HEAD" => HttpMethod::HEAD,
+            "OPTIONS" => HttpMethod::OPTIONS,
+            _ => return self.bad_request(buf_reader.get_mut(), &received, "unsupported method"),
+        };
+        // Split the target into the path used for routing and the query string
+        let (path, query_string) = match http_captures[2].split_once('?') {
+            Some((p, q)) => (p, q),
+            None => (&http_captures[2], ""),
+        };
+        // Route on the normalized path, so `//users` and `/a/../users` reach
+        // the same handler as `/users`
+        let path = match normalize_path(path) {
+            Some(path) => path,
+            None => return self.bad_request(buf_reader.get_mut(), &received, "invalid path"),
+        };
+        let protocol = &http_captures[3];
+
+        let version = match protocol {
+            "HTTP/1.0" => HttpVersion::Http10,
+            "HTTP/1.1" => HttpVersion::Http11,
+            _ if is_http_version(protocol) => {
+                let resp = HttpResponse::new(505, "HTTP Version Not Supported");
+                return self.respond_and_close(buf_reader.get_mut(), &received, resp);
+            }
+            _ => {
+                return self.bad_request(
+                    buf_reader.get_mut(),
+                    &received,
+                    "expected an HTTP protocol version",
+                )
+            }
+        };
+
+        received.line = Some((method, &path, version));
+
+        // Parse the header lines that follow the request line
+        let headers = match parse_headers(&http_request[1..]) {
+            Ok(headers) => headers,
+            Err(_) => {
+                return self.bad_request(buf_reader.get_mut(), &received, "malformed header line")
+            }
+        };
+        let id = request_id(find_header(&headers, "x-request-id"));
+        received.request_id = Some(&id);
+
+        // HTTP/1.1 connections persist unless the client asks to close them;
+        // older clients have to ask for keep-alive explicitly
+        let mut keep_alive = match find_header(&headers, "connection") {
+            Some(v) if has_token(v, "close") => false,
+            Some(v) if has_token(v, "keep-alive") => true,
+            _ => version == HttpVersion::Http11,
+        };
+
+        // A client that sends `Expect: 100-continue` waits for an interim
+        // response before sending the body. HTTP/1.0 clients cannot ask for
+        // one, so the header is ignored for them.
+        let mut expect_continue = false;
+        if version == HttpVersion::Http11 {
+            match find_header(&headers, "expect") {
+                None => {}
+                Some(v) if v.trim().eq_ignore_ascii_case("100-continue") => expect_continue = true,
+                Some(_) => {
+                    let resp = HttpResponse::new(417, "Expectation Failed");
+                    return self.respond_and_close(buf_reader.get_mut(), &received, resp);
+                }
+            }
+        }
+
+        // Read the body, if any, as framed by Transfer-Encoding or Content-Length.
+        // Without chunked as the final coding the body's end is unknown, and
+        // chunked is the only coding supported.
+        let chunked = match find_header(&headers, "transfer-encoding") {
+            None => false,
+            Some(v) => {
+                let codings: Vec<String> = v
+                    .split(',')
+                    .map(|c| c.trim().to_ascii_lowercase())
+                    .collect();
+                // Splitting always yields at least one coding
+                let (rest, last) = codings.split_at(codings.len() - 1);
+                if last[0] != "chunked" || rest.iter().any(|c| c == "chunked" || c.is_empty()) {
+                    return self.bad_request(
+                        buf_reader.get_mut(),
+                        &received,
+                        "Transfer-Encoding must end with a single chunked",
+                    );
+                }
+                if !rest.is_empty() {
+                    let resp =
+                        HttpResponse::new(501, "Not Implemented: unsupported transfer coding");
+                    return self.respond_and_close(buf_reader.get_mut(), &received, resp);
+                }
+                true
+            }
+        };
+        // A proxy in front may have framed a request with both headers by
+        // Content-Length. Its Content-Length is ignored (RFC 9112, section
+        // 6.3), and whatever follows the chunked body is not trusted.
+        if chunked && find_header(&headers, "content-length").is_some() {
+            keep_alive = false;
+        }
+        let mut content_length = 0;
+        if !chunked {
+            content_length = match find_header(&headers, "content-length") {
+                Some(v) => match parse_content_length(v) {
+                    Some(n) => n,
+                    None => {
+                        return self.bad_request(
+                            buf_reader.get_mut(),
+                            &received,
+                            "invalid Content-Length",
+                        )
+                    }
+                },
+                None => 0,
+            };
+        }
+        // Streaming handlers read the body themselves
+        let streaming = match self.streaming_router.find(method, &path) {
+            RouteMatch::Found(handler, params, template) => Some((handler, params, template)),
+            _ => None,
+        };
+        let body_bytes = if streaming.is_some() {
+            Vec::new()
+        } else if chunked {
+            if expect_continue {
+                send_continue(buf_reader.get_mut())?;
+            }
+            match read_chunked(buf_reader, self.max_body_size, self.trailer_limits()) {
+                Ok(bytes) => bytes,
+                Err(BodyError::Malformed(msg)) => {
+                    let msg = format!("Bad Request: {msg}");
+                    return self.respond_and_close(
+                        buf_reader.get_mut(),
+                        &received,
+                        HttpResponse::new(400, msg),
+                    );
+                }
+                Err(BodyError::TooLarge) => {
+                    return self.respond_and_close(
+                        buf_reader.get_mut(),
+                        &received,
+                        HttpResponse::new(413, "Content Too Large"),
+                    );
+                }
+                Err(BodyError::Io(err)) if is_timeout(&err) => {
+                    return self.respond_and_close(
+                        buf_reader.get_mut(),
+                        &received,
+                        HttpResponse::new(408, "Request Timeout"),
+                    );
+                }
+                Err(BodyError::Io(err)) if err.kind() == ErrorKind::UnexpectedEof => {
+                    return self.bad_request(
+                        buf_reader.get_mut(),
+                        &received,
+                        "body ended before its last chunk",
+                    )
+                }
+                Err(BodyError::Io(err)) => return Err(err),
+            }
+        } else {
+            // Reject oversized bodies before allocating anything for them. The
+            // unread body is still on the socket, so the connection is closed.
+            // A client waiting to continue gets this instead of `100 Continue`
+            // and never sends the body.
+            if content_length > self.max_body_size {
+                return self.respond_and_close(
+                    buf_reader.get_mut(),
+                    &received,
+                    HttpResponse::new(413, "Content Too Large"),
+                );
+            }
+            if expect_continue && content_length > 0 {
+                send_continue(buf_reader.get_mut())?;
+            }
+            // Exactly Content-Length bytes are read. Anything after them is
+            // the next request, which gets a 400 if it is not one; a client
+            // that hangs up early gets one for this request.
+            let mut body_bytes = vec![0; content_length];
+            match buf_reader.read_exact(&mut body_bytes) {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                    return self.bad_request(
+                        buf_reader.get_mut(),
+                        &received,
+                        "body is shorter than its Content-Length",
+                    )
+                }
+                Err(err) if is_timeout(&err) => {
+                    return self.respond_and_close(
+                        buf_reader.get_mut(),
+                        &received,
+                        HttpResponse::new(408, "Request Timeout"),
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+            body_bytes
+        };
+        let http_request: HttpRequest = HttpRequest {
+            method,
+            version,
+            path: path.clone(),
+            params: HashMap::new(),
+            query: parse_query(query_string),
+            query_string: query_string.to_string(),
+            headers,
+            body: body_bytes,
+            remote_addr,
+            request_id: id.clone(),
+            matched_route: None,
+            state: Arc::clone(&self.state),
+        };
+
+        let in_flight = self.metrics.start_request();
+        let (mut resp, route) = match streaming {
+            Some((handler, params, template)) => {
+                if expect_continue && (chunked || content_length > 0) {
+                    send_continue(buf_reader.get_mut())?;
+                }
+                let mut body = BodyReader::new(
+                    buf_reader,
+                    (!chunked).then_some(content_length),
+                    self.trailer_limits(),
+                );
+                let body_cell = RefCell::new(&mut body);
+                let dispatched = self.dispatch_to(http_request, &|mut req, matched_route| {
+                    req.matched_route = Some(template.to_string());
+                    matched_route.set(Some(template.to_string()));
+                    req.params = decode_params(params.clone());
+                    let path = req.path.clone();
+                    let mut body = body_cell.borrow_mut();
+                    return guard_panics(&self.name, &path, self.catch_panics, || {
+                        handler(req, &mut body)
+                    });
+                });
+                // The rest of an unread body is still on the connection
+                if !body.is_finished() {
+                    keep_alive = false;
+                }
+                dispatched
+            }
+            None => self.dispatch(http_request),
+        };
+        #[cfg(feature = "websocket")]
+        let upgrade = match resp.status {
+            101 => resp.upgrade.take(),
+            _ => None,
+        };
+        let status = resp.status;
+        // A handler may ask for the connection to be closed, and a server
+        // that is shutting down takes no more requests on it
+        if resp
+            .header("connection")
+            .is_some_and(|v| has_token(v, "close"))
+            || self.shutdown.is_requested()
+        {
+            keep_alive = false;
+        }
+        if version == HttpVersion::Http10 && resp.body_stream.is_some() && !resp.omit_body {
+            // The end of a streamed body is signalled by closing
+            keep_alive = false;
+        }
+        // The client learns whether it may send another request; a switch
+        // of protocols keeps its own `Connection: Upgrade`
+        if status != 101 {
+            if !keep_alive {
+                resp.set_header("Connection", "close");
+            } else if version == HttpVersion::Http10 {
+                // HTTP/1.0 clients only reuse connections they are told to
+                resp.set_header("Connection", "keep-alive");
+            }
+        }
+        let bytes = self.write_response(buf_reader.get_mut(), resp)?;
+
+        #[cfg(feature = "prometheus")]
+        if let Some(histogram) = &self.duration_histogram {
+            histogram.observe(start.elapsed());
+        }
+        self.log_access(&received, route.as_deref(), &id, status, bytes);
+        drop(in_flight);
+
+        #[cfg(feature = "websocket")]
+        if let Some(upgrade) = upgrade {
+            // An open WebSocket may stay idle for as long as it likes
+            buf_reader.get_ref().set_read_timeout(None)?;
+            upgrade(&mut Upgraded(buf_reader))?;
+            return Ok(false);
+        }
+        return Ok(keep_alive);
+    }
+
+    /// Produces the response to a parsed request: runs the middleware chain
+    /// around routing and the matched handler, and prepares the response for
+    /// sending. Also returns the template of the route that matched, if any.
+    fn dispatch(&self, req: HttpRequest) -> (HttpResponse, Option<String>) {
+        return self.dispatch_to(req, &|req, matched_route| self.route(req, matched_route));
+    }
+
+    /// Like `dispatch`, but with `endpoint` in place of routing the request.
+    fn dispatch_to(&self, req: HttpRequest, endpoint: Endpoint) -> (HttpResponse, Option<String>) {
+        let method = req.method;
+        let version = req.version;
+        let request_id = req.request_id.clone();
+        #[cfg(feature = "compression")]
+        let accept_encoding = req.header("accept-encoding").map(|v| v.to_string());
+        let if_none_match = req.header("if-none-match").map(|v| v.to_string());
+        let if_modified_since = req.header("if-modified-since").map(|v| v.to_string());
+
+        let matched_route: Cell<Option<String>> = Cell::new(None);
+        let resp = run_chain(&self.middleware, req, &|req| {
+            return endpoint(req, &matched_route);
+        });
+        let mut resp = match resp {
+            Ok(mut r) => match r.error.take() {
+                // Keep the headers middleware added, but none that describe
+                // the body or target of the response that is not sent
+                Some(err) => {
+                    let mut converted = self.error_response(err);
+                    for (name, value) in &r.headers {
+                        let describes_body = [
+                            "content-type",
+                            "content-encoding",
+                            "content-range",
+                            "etag",
+                            "last-modified",
+                            "location",
+                        ]
+                        .iter()
+                        .any(|h| h.eq_ignore_ascii_case(name));
+                        if !describes_body && converted.header(name).is_none() {
+                            converted.set_header(name, value);
+                        }
+                    }
+                    converted
+                }
+                None => r,
+            },
+            Err(err) => self.error_response(err),
+        };
+
+        let conditional =
+            matches!(method, HttpMethod::GET | HttpMethod::HEAD) && resp.status == 200;
+        if conditional && is_not_modified(&resp, if_none_match, if_modified_since) {
+            resp = not_modified(&resp);
+        }
+
+        #[cfg(feature = "compression")]
+        if let Some(min_size) = self.compression_min_size {
+            compression::compress(&mut resp, accept_encoding.as_deref(), min_size);
+        }
+        if resp.header("x-request-id").is_none() {
+            resp.set_header("X-Request-Id", &request_id);
+        }
+        resp.version = version;
+        if method == HttpMethod::HEAD {
+            resp.omit_body = true;
+        }
+        return (resp, matched_route.take());
+    }
+
+    /// Answers a handler's error with the error handler, if one is set.
+    fn error_response(&self, err: ServerError) -> HttpResponse {
+        return match self.error_handler {
+            Some(handler) => handler(err),
+            None if matches!(err, ServerError::BadRequest(_)) => {
+                HttpResponse::new(400, err.to_string())
+            }
+            None if self.expose_errors => HttpResponse::new(500, err.to_string()),
+            None => {
+                println!("Error in handler: {err}");
+                HttpResponse::new(500, "Internal Server Error")
+            }
+        };
+    }
+
+    /// Trailer fields count against the same limits as header lines.
+    fn trailer_limits(&self) -> TrailerLimits {
+        return TrailerLimits {
+            max_fields: self.max_headers,
+            max_bytes: self.max_header_bytes,
+        };
+    }
+
+    /// Answers a request that could not be parsed with `400 Bad Request` and
+    /// the reason, then closes the connection, since the rest of what the
+    /// client sent cannot be trusted to be framed correctly.
+    fn bad_request(
+        &self,
+        stream: &mut impl Write,
+        received: &Received,
+        reason: &str,
+    ) -> Result<bool, Error> {
+        let resp = HttpResponse::new(400, format!("Bad Request: {reason}"));
+        return self.respond_and_close(stream, received, resp);
+    }
+
+    /// Writes a response after which the connection is closed, telling the
+    /// client so with `Connection: close`, and logs it with what is known of
+    /// the request.
+    fn respond_and_close(
+        &self,
+        stream: &mut impl Write,
+        received: &Received,
+        mut resp: HttpResponse,
+    ) -> Result<bool, Error> {
+        let id = match received.request_id {
+            Some(id) => id.to_string(),
+            None => request_id(None),
+        };
+        let status = resp.status;
+        resp.set_header("Connection", "close");
+        resp.set_header("X-Request-Id", &id);
+        let bytes = self.write_response(stream, resp)?;
+        self.log_access(received, None, &id, status, bytes);
+        return Ok(false);
+    }
+
+    /// Passes a written response to the access logger.
+    fn log_access(
+        &self,
+        received: &Received,
+        route: Option<&str>,
+        request_id: &str,
+        status: u16,
+        bytes: usize,
+    ) {
+        (self.access_logger)(&AccessLogEntry {
+            method: received.line.map(|(method, _, _)| method),
+            version: received.line.map(|(_, _, version)| version),
+            path: received.line.map(|(_, path, _)| path),
+            route,
+            remote_addr: received.remote_addr,
+            request_id,
+            status,
+            bytes,
+            duration: received.start.elapsed(),
+        });
+    }
+
+    /// Finds the handler for the request's method and path and calls it,
+    /// recording the template of the matched route in `matched_route`.
+    fn route(
+        &self,
+        mut req: HttpRequest,
+        matched_route: &Cell<Option<String>>,
+    ) -> Result<HttpResponse, ServerError> {
+        let mut found = self.find_route(req.method, &req.path);
+        let retry_trimmed = self.trailing_slash != TrailingSlash::Strict
+            && matches!(found, RouteMatch::NotFound)
+            && req.path.len() > 1
+            && req.path.ends_with('/');
+        if retry_trimmed {
+            let trimmed = match req.path.trim_end_matches('/') {
+                "" => "/".to_string(),
+                trimmed => trimmed.to_string(),
+            };
+            match self.find_route(req.method, &trimmed) {
+                RouteMatch::NotFound => {}
+                _ if self.trailing_slash == TrailingSlash::Redirect => {
+                    // Normalization leaves no `//` that would make the location
+                    // point at another host
+                    let mut location = encode_path(&trimmed);
+                    if !req.query_string.is_empty() {
+                        location.push('?');
+                        location.push_str(&req.query_string);
+                    }
+                    // 308 keeps the method and body; 301 may turn them into GET
+                    if !matches!(req.method, HttpMethod::GET | HttpMethod::HEAD) {
+                        return Ok(HttpResponse::redirect_with_status(308, &location));
+                    }
+                    return Ok(HttpResponse::redirect_permanent(&location));
+                }
+                other => {
+                    found = other;
+                    req.path = trimmed;
+                }
+            }
+        }
+        return match found {
+            RouteMatch::Found(handler, params, template) => {
+                req.matched_route = Some(template.to_string());
+                matched_route.set(Some(template.to_string()));
+                req.params = decode_params(params);
+                self.call_handler(handler, req)
+            }
+            // Without an OPTIONS route of its own, a path answers OPTIONS
+            // with the methods it supports
+            RouteMatch::MethodNotAllowed if req.method == HttpMethod::OPTIONS => {
+                let mut resp = HttpResponse::new(204, "");
+                resp.set_header("Allow", &self.allow_header(&req.path));
+                Ok(resp)
+            }
+            RouteMatch::MethodNotAllowed => {
+                let mut resp = HttpResponse::new(405, "Method Not Allowed");
+                resp.set_header("Allow", &self.allow_header(&req.path));
+                Ok(resp)
+            }
+            RouteMatch::NotFound => self.call_handler(&self.not_found_handler, req),
+        };
+    }
+
+    /// Looks up the route for a method and path. Without a HEAD route, HEAD
+    /// requests use the GET route; the body is dropped when the response is
+    /// written.
+    fn find_route(&self, method: HttpMethod, path: &str) -> RouteMatch<'_, Arc<Handler>> {
+        let found = self.router.find(method, path);
+        if method == HttpMethod::HEAD && !matches!(found, RouteMatch::Found(..)) {
+            if let get @ RouteMatch::Found(..) = self.router.find(HttpMethod::GET, path) {
+                return get;
+            }
+        }
+        return found;
+    }
+
+    /// Returns the `Allow` header value for a path that has routes: their
+    /// methods, plus HEAD for GET routes and OPTIONS, which is always
+    /// answered.
+    fn allow_header(&self, path: &str) -> String {
+        let mut methods = self.router.methods_for(path);
+        if methods.contains(&HttpMethod::GET) && !methods.contains(&HttpMethod::HEAD) {
+            methods.push(HttpMethod::HEAD);
+        }
+        if !methods.contains(&HttpMethod::OPTIONS) {
+            methods.push(HttpMethod::OPTIONS);
+        }
+        return methods
+            .iter()
+            .map(|m| m.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+    }
+
+    /// Calls a handler, on a thread of its own if a handler timeout is set,
+    /// answering `503 Service Unavailable` if it does not return in time.
+    fn call_handler(
+        &self,
+        handler: &Arc<Handler>,
+        req: HttpRequest,
+    ) -> Result<HttpResponse, ServerError> {
+        let timeout = match self.handler_timeout {
+            Some(timeout) => timeout,
+            None => {
+                let path = req.path.clone();
+                return guard_panics(&self.name, &path, self.catch_panics, || handler(req));
+            }
+        };
+        let handler = Arc::clone(handler);
+        let path = req.path.clone();
+        let name = self.name.clone();
+        let catch_panics = self.catch_panics;
+        let (sender, receiver) = mpsc::channel();
+        let _ = thread::Builder::new()
+            .name(format!("{0}-handler", self.name))
+            .spawn(move || {
+                let path = req.path.clone();
+                let result = guard_panics(&name, &path, catch_panics, || handler(req));
+                // The receiver is gone if the handler timed out
+                let _ = sender.send(result);
+            })?;
+        return match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                println!(
+                    "RestServer [{0}]: handler for {path} timed out after {1} ms",
+                    self.name,
+                    timeout.as_millis()
+                );
+                Ok(HttpResponse::new(503, "Service Unavailable"))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(ServerError::HandlerError(format!(
+                "handler for {path} panicked"
+            ))),
+        };
+    }
+
+    /// Writes a response to the stream, identifying this server in the
+    /// `Server` header unless the handler set its own, and adding the
+    /// default headers.
+    /// Returns the number of body bytes written.
+    fn write_response(
+        &self,
+        stream: &mut impl Write,
+        mut resp: HttpResponse,
+    ) -> Result<usize, Error> {
+        if resp.header("server").is_none() {
+            resp.set_header("Server", &self.name);
+        }
+        for (name, value) in &self.default_headers {
+            if resp.header(name).is_none() {
+                resp.set_header(name, value);
+            }
+        }
+        self.metrics.record_response(resp.status);
+        if self.buffer_responses {
+            let mut buffered = BufWriter::new(stream);
+            let written = self.send_response(&mut buffered, resp)?;
+            buffered.flush()?;
+            return Ok(written);
+        }
+        return self.send_response(stream, resp);
+    }
+
+    /// Writes the head and body of a prepared response and flushes it,
+    /// returning the number of body bytes sent.
+    fn send_response(
+        &self,
+        stream: &mut impl Write,
+        mut resp: HttpResponse,
+    ) -> Result<usize, Error> {
+        let head = resp.serialize_head();
+        stream.write_all(head.as_bytes())?;
+        if resp.omit_body || status_has_no_body(resp.status) {
+            stream.flush()?;
+            return Ok(0);
+        }
+        let written = match resp.body_stream.take() {
+            None => {
+                stream.write_all(resp.wire_body())?;
+                stream.flush()?;
+                Ok(resp.wire_body().len())
+            }
+            Some(produce) if resp.version == HttpVersion::Http10 => {
+                let mut writer = CountingWriter::new(stream);
+                produce(&mut writer)?;
+                writer.finish()
+            }
+            Some(produce) => {
+                let mut writer = ChunkedWriter::new(stream);
+                produce(&mut writer)?;
+                writer.finish()
+            }
+        }?;
+        self.metrics.record_bytes(written);
+        return Ok(written);
+    }
+}
+
+/// Returns true if the validators a client sent show that its cached copy
+/// of `resp` is current. `If-Modified-Since` only counts without
+/// `If-None-Match`.
+fn is_not_modified(
+    resp: &HttpResponse,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+) -> bool {
+    if let Some(if_none_match) = if_none_match {
+        return resp
+            .header("etag")
+            .is_some_and(|etag| none_match_hit(&if_none_match, etag));
+    }
+    let since = if_modified_since.and_then(|v| parse_http_date(&v));
+    let modified = resp.header("last-modified").and_then(parse_http_date);
+    return match (since, modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    };
+}
+
+/// Builds the `304 Not Modified` answer to a conditional request for
+/// `resp`, keeping the headers a cache needs to update its copy.
+fn not_modified(resp: &HttpResponse) -> HttpResponse {
+    let mut not_modified = HttpResponse::new(304, "");
+    for name in [
+        "Cache-Control",
+        "Content-Location",
+        "Date",
+        "ETag",
+        "Expires",
+        "Last-Modified",
+        "Vary",
+    ] {
+        if let Some(value) = resp.header(name) {
+            not_modified.set_header(name, value);
+        }
+    }
+    return not_modified;
+}
+
+/// Sets `SO_REUSEPORT` on a socket.
+#[cfg(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+))]
+fn set_reuse_port(socket: &Socket) -> Result<(), Error> {
+    return socket.set_reuse_port(true);
+}
+
+/// Fails, since the platform has no `SO_REUSEPORT`.
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+)))]
+fn set_reuse_port(_socket: &Socket) -> Result<(), Error> {
+    return Err(Error::new(
+        ErrorKind::Unsupported,
+        "SO_REUSEPORT is not supported on this platform",
+    ));
+}
+
+/// Decodes the percent-encoded values of captured path parameters.
+fn decode_params(params: HashMap<String, String>) -> HashMap<String, String> {
+    return params
+        .into_iter()
+        .map(|(k, v)| (k, percent_decode(&v).into_owned()))
+        .collect();
+}
+
+/// Runs a handler for `path`. Unless `catch_panics` is off, a panic is
+/// logged and turned into an error, so the calling thread survives it.
+fn guard_panics<F>(
+    server_name: &str,
+    path: &str,
+    catch_panics: bool,
+    handler: F,
+) -> Result<HttpResponse, ServerError>
+where
+    F: FnOnce() -> Result<HttpResponse, ServerError>,
+{
+    if !catch_panics {
+        return handler();
+    }
+    return match panic::catch_unwind(AssertUnwindSafe(handler)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let msg = panic_message(&*payload);
+            println!("RestServer [{server_name}]: handler for {path} panicked: {msg}");
+            Err(ServerError::HandlerError(format!(
+                "handler for {path} panicked: {msg}"
+            )))
+        }
+    };
+}
+
+/// Returns the message a panic was raised with.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    return match payload.downcast_ref::<&str>() {
+        Some(msg) => msg.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(msg) => msg.clone(),
+            None => "unknown panic".to_string(),
+        },
+    };
+}
+
+/// Builds a response like `{"status":"ok"}` for the health endpoints.
+fn json_status(status: u16, text: &str) -> HttpResponse {
+    let mut resp = HttpResponse::new(status, format!("{{\"status\":\"{text}\"}}"));
+    resp.set_header("Content-Type", "application/json");
+    return resp;
+}
+
+/// Returns true if `protocol` is a well-formed version like `HTTP/2.0`,
+/// whether or not it is supported.
+fn is_http_version(protocol: &str) -> bool {
+    return match protocol.strip_prefix("HTTP/").map(|v| v.as_bytes()) {
+        Some([major, b'.', minor]) => major.is_ascii_digit() && minor.is_ascii_digit(),
+        _ => false,
+    };
+}
+
+/// Returns true if `name` is a valid header name and `value` cannot end the
+/// header line early: it holds no CR, LF or NUL.
+///
+/// ```
+/// use rustful::rest_server::is_valid_header;
+///
+/// assert!(is_valid_header("X-Trace", "a b\tc"));
+/// assert!(!is_valid_header("X-A", "a\r\nSet-Cookie: evil=1"));
+/// assert!(!is_valid_header("X A", "1"));
+/// assert!(!is_valid_header("", "1"));
+/// ```
+pub fn is_valid_header(name: &str, value: &str) -> bool {
+    return is_token(name) && !value.contains(['\r', '\n', '\0']);
+}
+
+/// Returns true if `s` is a non-empty token (RFC 9110, section 5.6.2), the
+/// syntax of header names.
+pub(crate) fn is_token(s: &str) -> bool {
+    return !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+}
+
+/// Returns true if a comma-separated header value contains the given token.
+pub(crate) fn has_token(value: &str, token: &str) -> bool {
+    return value
+        .split(',')
+        .any(|t| t.trim().eq_ignore_ascii_case(token));
+}
+
+/// Returns the pause after another accept error in a row: twice the last
+/// one, up to `ACCEPT_BACKOFF_MAX`.
+fn next_backoff(backoff: Duration) -> Duration {
+    return backoff.saturating_mul(2).min(ACCEPT_BACKOFF_MAX);
+}
+
+/// Parses a `Content-Length` value, which must be decimal digits only. A
+/// repeated header arrives joined with commas and is accepted only if every
+/// copy has the same value, so the body cannot be framed two ways.
+fn parse_content_length(value: &str) -> Option<usize> {
+    let mut length: Option<usize> = None;
+    for part in value.split(',') {
+        let part = part.trim();
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let n = part.parse::<usize>().ok()?;
+        if length.is_some_and(|length| length != n) {
+            return None;
+        }
+        length = Some(n);
+    }
+    return length;
+}
+
+/// Socket timeouts surface as `WouldBlock` on Unix and `TimedOut` on Windows.
+fn is_timeout(err: &Error) -> bool {
+    return matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut);
+}
+
+/// Parses raw header lines into pairs of lowercased name and value, in the
+/// order they were sent. Repeated headers are joined with ", " as allowed
+/// by RFC 7230. Fails if a name is not a token, e.g. when whitespace comes
+/// before its colon.
+fn parse_headers(lines: &[String]) -> Result<Vec<(String, String)>, Error> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines {
+        let (key, value) = match line.split_once(':') {
+            Some(kv) => kv,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("HTTP request is invalid: malformed header line: {line}"),
+                ))
+            }
+        };
+        // No whitespace may come before the colon (RFC 9112, section 5.1),
+        // and a value may not hide a line break or NUL
+        let value = value.trim_matches([' ', '\t']);
+        if !is_token(key) || value.contains(['\r', '\0']) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("HTTP request is invalid: malformed header line: {line}"),
+            ));
+        }
+        let key = key.to_ascii_lowercase();
+        match headers.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => {
+                existing.push_str(", ");
+                existing.push_str(value);
+            }
+            None => headers.push((key, value.to_string())),
+        }
+    }
+    return Ok(headers);
+}
+
+/// Looks up a header by name, case-insensitively.
+fn find_header<'h>(headers: &'h [(String, String)], name: &str) -> Option<&'h str> {
+    return headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str());
+}
+
+/// Returns the credentials of an `Authorization` header value if it uses
+/// the given scheme, which is matched case-insensitively.
+fn auth_credentials<'h>(value: &'h str, scheme: &str) -> Option<&'h str> {
+    let (name, credentials) = value.trim().split_once(' ')?;
+    if !name.eq_ignore_ascii_case(scheme) {
+        return None;
+    }
+    let credentials = credentials.trim();
+    if credentials.is_empty() || credentials.contains(' ') {
+        return None;
+    }
+    return Some(credentials);
+}
+
+/// Parses a query string such as `q=rust&page=2` into decoded key/value pairs.
+/// A key without a value maps to an empty string.
+/// Later values of a repeated key replace earlier ones.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    return parse_urlencoded(query).into_iter().collect();
+}
+
+/// Tells a client that sent `Expect: 100-continue` to go ahead with the
+/// body. The final response follows once the request has been handled.
+fn send_continue(stream: &mut impl Write) -> Result<(), Error> {
+    stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    return stream.flush();
+}
+
+/// Parses `application/x-www-form-urlencoded` data, the format of query
+/// strings and form bodies, into decoded pairs in their original order.
+fn parse_urlencoded(data: &str) -> Vec<(String, String)> {
+    return data
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (
+                form_decode(key).into_owned(),
+                form_decode(value).into_owned(),
+            )
+        })
+        .collect();
+}
+
+// Handler for /ping
+pub fn handle_ping(_req: HttpRequest) -> HandlerResult {
+    return ok("pong");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use std::io::{Error, ErrorKind, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use super::{
+        http_regex, next_backoff, parse_content_length, RestServer, ACCEPT_BACKOFF_MAX,
+        ACCEPT_BACKOFF_MIN,
+    };
+    use crate::thread_pool::ThreadPool;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let mut backoff = ACCEPT_BACKOFF_MIN;
+        let mut pauses: Vec<u128> = Vec::new();
+        for _ in 0..10 {
+            pauses.push(backoff.as_millis());
+            backoff = next_backoff(backoff);
+        }
+        assert_eq!(pauses, [10, 20, 40, 80, 160, 320, 640, 1000, 1000, 1000]);
+        assert_eq!(next_backoff(ACCEPT_BACKOFF_MAX), ACCEPT_BACKOFF_MAX);
+        assert_eq!(next_backoff(Duration::MAX), ACCEPT_BACKOFF_MAX);
+    }
+
+    #[test]
+    fn accept_loop_survives_accept_errors() {
+        let mut svr = RestServer::new("test", "127.0.0.1", 0).unwrap();
+        svr.set_access_logger(|_| {});
+        svr.register_path("/", |_req| Ok("hi")).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            return response;
+        });
+
+        // Out of file descriptors three times, then one real connection
+        let failures = (0..3).map(|_| Err(Error::from_raw_os_error(24)));
+        let aborted = Err(Error::from(ErrorKind::ConnectionAborted));
+        let incoming = failures.chain([aborted]).chain(listener.incoming().take(1));
+        thread::scope(|scope| {
+            let pool = ThreadPool::new(scope, 1);
+            svr.accept_loop(incoming, &pool).unwrap();
+        });
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("hi"), "{response}");
+    }
+
+    #[test]
+    fn content_length_must_be_digits() {
+        assert_eq!(parse_content_length("0"), Some(0));
+        assert_eq!(parse_content_length("42"), Some(42));
+        assert_eq!(parse_content_length("007"), Some(7));
+        assert_eq!(parse_content_length("5, 5"), Some(5));
+        for invalid in [
+            "",
+            "+2",
+            "-1",
+            " ",
+            "0x10",
+            "1e3",
+            "1 2",
+            "5, 6",
+            "5,",
+            "99999999999999999999999",
+        ] {
+            assert_eq!(parse_content_length(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn request_line_regex() {
+        let captures = http_regex().captures("GET /a?b=c HTTP/1.1").unwrap();
+        assert_eq!(&captures[1], "GET");
+        assert_eq!(&captures[2], "/a?b=c");
+        assert_eq!(&captures[3], "HTTP/1.1");
+        assert!(http_regex().is_match("delete /a/b HTTP/1.0"));
+        for invalid in [
+            "GET /a",
+            "GET /a ",
+            "GET  /a HTTP/1.1",
+            "GET /a  HTTP/1.1",
+            " GET /a HTTP/1.1",
+            "GET /a#top HTTP/1.1",
+            "GET a HTTP/1.1",
+            "GET * HTTP/1.1",
+            "GET /a HTTP/1.1 extra",
+        ] {
+            assert!(!http_regex().is_match(invalid), "{invalid:?}");
+        }
+    }
 }
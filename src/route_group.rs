@@ -0,0 +1,75 @@
+use std::sync::{Arc, RwLock};
+
+use crate::error::ServerError;
+use crate::middleware::{run_chain, Middleware};
+use crate::rest_server::{Handler, HttpMethod, HttpRequest, IntoResponse, RestServer};
+
+/// Middleware shared by every route of a group. Routes registered before a
+/// middleware is added still run it.
+type GroupChain = Arc<RwLock<Vec<Box<dyn Middleware>>>>;
+
+/// RouteGroup registers routes under a common path prefix.
+///
+/// ```no_run
+/// use rustful::rest_server::{self, RestServer};
+///
+/// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+/// let mut v1 = svr.group("/api/v1");
+/// v1.register_path("/ping", rest_server::handle_ping).unwrap(); // GET /api/v1/ping
+/// ```
+pub struct RouteGroup<'s> {
+    server: &'s mut RestServer,
+    prefix: String,
+    chain: GroupChain,
+}
+
+impl<'s> RouteGroup<'s> {
+    pub(crate) fn new(server: &'s mut RestServer, prefix: &str) -> Self {
+        return RouteGroup {
+            server,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            chain: Arc::new(RwLock::new(Vec::new())),
+        };
+    }
+
+    /// Adds a GET handler to the specified path below the group prefix.
+    pub fn register_path<R, F>(&mut self, path: &str, func: F) -> Result<(), ServerError>
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        return self.register(HttpMethod::GET, path, func);
+    }
+
+    /// Adds a handler for the specified method and path below the group
+    /// prefix. See `RestServer::register` for the path syntax.
+    pub fn register<R, F>(
+        &mut self,
+        method: HttpMethod,
+        path: &str,
+        func: F,
+    ) -> Result<(), ServerError>
+    where
+        R: IntoResponse + 'static,
+        F: Fn(HttpRequest) -> Result<R, ServerError> + Send + Sync + 'static,
+    {
+        let chain = Arc::clone(&self.chain);
+        let handler: Handler = Box::new(move |req| {
+            let chain = chain.read().unwrap_or_else(|e| e.into_inner());
+            return run_chain(&chain, req, &|req| {
+                func(req).map(IntoResponse::into_response)
+            });
+        });
+        let full_path = self.prefix.clone() + path;
+        return self.server.add_route(method, &full_path, handler);
+    }
+
+    /// Adds a middleware that runs around the routes of this group only,
+    /// inside any middleware added with `RestServer::use_middleware`.
+    pub fn use_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
+        self.chain
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::new(middleware));
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::rest_server::HttpMethod;
+
+/// A single `/`-separated piece of a registered route template.
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    /// Matches the segment text exactly, e.g. `users`.
+    Static(String),
+    /// Matches any non-empty segment and captures it, e.g. `:id`.
+    Param(String),
+    /// Matches the rest of the path, which may be empty, and captures it,
+    /// e.g. `*path`. Only allowed as the last segment.
+    Wildcard(String),
+}
+
+impl Segment {
+    /// Two segments overlap if they would match the same incoming text. The
+    /// name of a parameter does not matter for this.
+    fn same_shape(&self, other: &Segment) -> bool {
+        return match (self, other) {
+            (Segment::Static(a), Segment::Static(b)) => a == b,
+            (Segment::Param(_), Segment::Param(_)) => true,
+            (Segment::Wildcard(_), Segment::Wildcard(_)) => true,
+            _ => false,
+        };
+    }
+}
+
+struct Route<H> {
+    method: HttpMethod,
+    /// The template as registered, e.g. `/users/:id`.
+    template: String,
+    segments: Vec<Segment>,
+    handler: H,
+}
+
+impl<H> Route<H> {
+    fn has_wildcard(&self) -> bool {
+        return matches!(self.segments.last(), Some(Segment::Wildcard(_)));
+    }
+}
+
+/// A route whose path is matched by a regular expression.
+struct RegexRoute<H> {
+    method: HttpMethod,
+    /// The pattern as registered, e.g. `/item/(\d+)`.
+    pattern: String,
+    /// The pattern anchored to match the whole path.
+    regex: Regex,
+    handler: H,
+}
+
+/// Orders matching routes: routes without a wildcard first, then by which
+/// segments are static, from left to right.
+type Rank = (bool, Vec<bool>);
+
+/// Why a route could not be added to the router.
+pub(crate) enum InsertError {
+    /// A route with the same method and shape already exists.
+    Duplicate,
+    /// The template itself is invalid, e.g. a wildcard that is not last.
+    InvalidTemplate(String),
+}
+
+/// Result of looking up a method and path in the router.
+pub(crate) enum RouteMatch<'r, H> {
+    /// A route matched; holds its handler, the captured path parameters and
+    /// the route's template.
+    Found(&'r H, HashMap<String, String>, &'r str),
+    /// The path matched one or more routes, but none for the method.
+    MethodNotAllowed,
+    /// No route matched the path.
+    NotFound,
+}
+
+/// Router matches incoming paths against registered route templates.
+///
+/// Templates are split on `/` and each segment is either static text, a
+/// `:name` parameter or, as the last segment, a `*name` wildcard capturing
+/// the rest of the path. When several routes match a path, the one with a
+/// static segment at the first position where they differ wins, so
+/// `/users/me` is preferred over `/users/:id`. Routes matched by a regular
+/// expression are tried next, in registration order, and wildcard routes
+/// only win when no other route matches.
+pub(crate) struct Router<H> {
+    routes: Vec<Route<H>>,
+    regex_routes: Vec<RegexRoute<H>>,
+}
+
+impl<H> Router<H> {
+    pub(crate) fn new() -> Self {
+        return Router {
+            routes: Vec::new(),
+            regex_routes: Vec::new(),
+        };
+    }
+
+    /// Adds a route matched by a regular expression, which must match the
+    /// whole path. Fails if the pattern is invalid or was already added for
+    /// the method.
+    pub(crate) fn insert_regex(
+        &mut self,
+        method: HttpMethod,
+        pattern: &str,
+        handler: H,
+    ) -> Result<(), InsertError> {
+        let regex = Regex::new(&format!("^(?:{pattern})$"))
+            .map_err(|err| InsertError::InvalidTemplate(format!("invalid regex: {err}")))?;
+        let duplicate = self
+            .regex_routes
+            .iter()
+            .any(|r| r.method == method && r.pattern == pattern);
+        if duplicate {
+            return Err(InsertError::Duplicate);
+        }
+        self.regex_routes.push(RegexRoute {
+            method,
+            pattern: pattern.to_string(),
+            regex,
+            handler,
+        });
+        return Ok(());
+    }
+
+    /// Adds a route. Fails if the template is invalid or a route with the
+    /// same method and shape exists.
+    pub(crate) fn insert(
+        &mut self,
+        method: HttpMethod,
+        template: &str,
+        handler: H,
+    ) -> Result<(), InsertError> {
+        let segments = self.new_segments(method, template)?;
+        self.routes.push(Route {
+            method,
+            template: template.to_string(),
+            segments,
+            handler,
+        });
+        return Ok(());
+    }
+
+    /// Checks that `insert` would accept the route, without adding it.
+    pub(crate) fn check_insert(
+        &self,
+        method: HttpMethod,
+        template: &str,
+    ) -> Result<(), InsertError> {
+        return self.new_segments(method, template).map(|_| ());
+    }
+
+    /// Parses the template of a route that is not registered yet.
+    fn new_segments(
+        &self,
+        method: HttpMethod,
+        template: &str,
+    ) -> Result<Vec<Segment>, InsertError> {
+        let segments = parse_template(template)?;
+        let duplicate = self.routes.iter().any(|r| {
+            r.method == method
+                && r.segments.len() == segments.len()
+                && r.segments
+                    .iter()
+                    .zip(&segments)
+                    .all(|(a, b)| a.same_shape(b))
+        });
+        if duplicate {
+            return Err(InsertError::Duplicate);
+        }
+        return Ok(segments);
+    }
+
+    /// Finds the best route for the given method and path.
+    pub(crate) fn find(&self, method: HttpMethod, path: &str) -> RouteMatch<'_, H> {
+        let parts = split_path(path);
+        let mut best: Option<(&Route<H>, Rank)> = None;
+        let mut path_matched = false;
+        for route in &self.routes {
+            if !segments_match(&route.segments, &parts) {
+                continue;
+            }
+            path_matched = true;
+            if route.method != method {
+                continue;
+            }
+            let rank: Rank = (
+                !route.has_wildcard(),
+                route
+                    .segments
+                    .iter()
+                    .map(|s| matches!(s, Segment::Static(_)))
+                    .collect(),
+            );
+            match &best {
+                Some((_, best_rank)) if *best_rank >= rank => {}
+                _ => best = Some((route, rank)),
+            }
+        }
+
+        // Regex routes come after static and parameter routes, but before
+        // wildcard routes
+        if best
+            .as_ref()
+            .map_or(true, |(route, _)| route.has_wildcard())
+        {
+            for route in &self.regex_routes {
+                let captures = match route.regex.captures(path) {
+                    Some(captures) => captures,
+                    None => continue,
+                };
+                path_matched = true;
+                if route.method == method {
+                    return RouteMatch::Found(
+                        &route.handler,
+                        regex_params(&route.regex, &captures),
+                        &route.pattern,
+                    );
+                }
+            }
+        }
+
+        return match best {
+            Some((route, _)) => RouteMatch::Found(
+                &route.handler,
+                capture(&route.segments, &parts),
+                &route.template,
+            ),
+            None if path_matched => RouteMatch::MethodNotAllowed,
+            None => RouteMatch::NotFound,
+        };
+    }
+
+    /// Returns the method and template or pattern of every route, in
+    /// registration order, with regex routes last.
+    pub(crate) fn routes(&self) -> impl Iterator<Item = (HttpMethod, &str)> {
+        let regex_routes = self
+            .regex_routes
+            .iter()
+            .map(|r| (r.method, r.pattern.as_str()));
+        return self
+            .routes
+            .iter()
+            .map(|r| (r.method, r.template.as_str()))
+            .chain(regex_routes);
+    }
+
+    /// Returns the methods of the routes matching `path`, in registration
+    /// order and without repeats.
+    pub(crate) fn methods_for(&self, path: &str) -> Vec<HttpMethod> {
+        let parts = split_path(path);
+        let mut methods: Vec<HttpMethod> = Vec::new();
+        for route in &self.routes {
+            if segments_match(&route.segments, &parts) && !methods.contains(&route.method) {
+                methods.push(route.method);
+            }
+        }
+        for route in &self.regex_routes {
+            if route.regex.is_match(path) && !methods.contains(&route.method) {
+                methods.push(route.method);
+            }
+        }
+        return methods;
+    }
+}
+
+fn parse_template(template: &str) -> Result<Vec<Segment>, InsertError> {
+    let parts = split_path(template);
+    let mut segments: Vec<Segment> = Vec::with_capacity(parts.len());
+    for (i, s) in parts.iter().enumerate() {
+        let segment = if let Some(name) = s.strip_prefix(':') {
+            Segment::Param(name.to_string())
+        } else if let Some(name) = s.strip_prefix('*') {
+            if i + 1 != parts.len() {
+                return Err(InsertError::InvalidTemplate(
+                    "a wildcard must be the last segment".to_string(),
+                ));
+            }
+            if name.is_empty() {
+                return Err(InsertError::InvalidTemplate(
+                    "a wildcard needs a name".to_string(),
+                ));
+            }
+            Segment::Wildcard(name.to_string())
+        } else {
+            Segment::Static(s.to_string())
+        };
+        segments.push(segment);
+    }
+    return Ok(segments);
+}
+
+/// Splits a path into segments after its leading `/`. A trailing slash yields
+/// a final empty segment, so `/users` and `/users/` are distinct.
+fn split_path(path: &str) -> Vec<&str> {
+    return path.strip_prefix('/').unwrap_or(path).split('/').collect();
+}
+
+fn segments_match(segments: &[Segment], parts: &[&str]) -> bool {
+    let fixed = match segments.last() {
+        // The wildcard also matches when the rest is missing entirely, so
+        // `/files/*path` matches `/files`
+        Some(Segment::Wildcard(_)) if parts.len() + 1 >= segments.len() => {
+            &segments[..segments.len() - 1]
+        }
+        Some(Segment::Wildcard(_)) => return false,
+        _ if segments.len() != parts.len() => return false,
+        _ => segments,
+    };
+    return fixed.iter().zip(parts).all(|(s, p)| match s {
+        Segment::Static(text) => text == p,
+        Segment::Param(_) => !p.is_empty(),
+        Segment::Wildcard(_) => true,
+    });
+}
+
+fn capture(segments: &[Segment], parts: &[&str]) -> HashMap<String, String> {
+    let mut params: HashMap<String, String> = HashMap::new();
+    for (i, segment) in segments.iter().enumerate() {
+        match segment {
+            Segment::Param(name) => {
+                let _ = params.insert(name.clone(), parts[i].to_string());
+            }
+            Segment::Wildcard(name) => {
+                let rest = parts.get(i..).unwrap_or(&[]).join("/");
+                let _ = params.insert(name.clone(), rest);
+            }
+            Segment::Static(_) => {}
+        }
+    }
+    return params;
+}
+
+/// Captures the groups of a regex route match by index, starting at `1`,
+/// and named groups by name as well. Groups that did not take part in the
+/// match are left out.
+fn regex_params(regex: &Regex, captures: &regex::Captures) -> HashMap<String, String> {
+    let mut params: HashMap<String, String> = HashMap::new();
+    for (i, name) in regex.capture_names().enumerate().skip(1) {
+        if let Some(m) = captures.get(i) {
+            let _ = params.insert(i.to_string(), m.as_str().to_string());
+            if let Some(name) = name {
+                let _ = params.insert(name.to_string(), m.as_str().to_string());
+            }
+        }
+    }
+    return params;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InsertError, RouteMatch, Router};
+    use crate::rest_server::HttpMethod;
+
+    #[test]
+    fn regex_route_matches_and_captures() {
+        let mut router: Router<u32> = Router::new();
+        assert!(router
+            .insert_regex(HttpMethod::GET, r"/item/(\d+)/(?P<slug>[a-z-]+)", 1)
+            .is_ok());
+        match router.find(HttpMethod::GET, "/item/42/blue-shoes") {
+            RouteMatch::Found(handler, params, pattern) => {
+                assert_eq!(*handler, 1);
+                assert_eq!(pattern, r"/item/(\d+)/(?P<slug>[a-z-]+)");
+                assert_eq!(params.get("1").map(String::as_str), Some("42"));
+                assert_eq!(params.get("2").map(String::as_str), Some("blue-shoes"));
+                assert_eq!(params.get("slug").map(String::as_str), Some("blue-shoes"));
+            }
+            _ => panic!("regex route did not match"),
+        }
+        // The pattern must match the whole path
+        assert!(matches!(
+            router.find(HttpMethod::GET, "/item/42/blue-shoes/x"),
+            RouteMatch::NotFound
+        ));
+        assert!(matches!(
+            router.find(HttpMethod::POST, "/item/42/blue-shoes"),
+            RouteMatch::MethodNotAllowed
+        ));
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        let mut router: Router<u32> = Router::new();
+        let result = router.insert_regex(HttpMethod::GET, r"/item/(\d+", 1);
+        assert!(matches!(result, Err(InsertError::InvalidTemplate(_))));
+        assert!(matches!(
+            router.find(HttpMethod::GET, "/item/1"),
+            RouteMatch::NotFound
+        ));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::error::ServerError;
+use crate::middleware::{Middleware, Next};
+use crate::rest_server::{HttpRequest, HttpResponse};
+
+/// Default `Strict-Transport-Security` value: one year.
+const DEFAULT_HSTS: &str = "max-age=31536000";
+
+/// Whether a `SecurityHeaders` middleware sends `Strict-Transport-Security`.
+#[derive(Clone)]
+enum Hsts {
+    /// Send the default value if the server serves TLS.
+    Auto,
+    /// Send the given value, or nothing.
+    Set(Option<String>),
+}
+
+/// SecurityHeaders is a middleware that adds common security headers to
+/// every response:
+///
+/// - `X-Content-Type-Options: nosniff`
+/// - `X-Frame-Options: DENY`
+/// - `Content-Security-Policy: default-src 'self'`
+/// - `Strict-Transport-Security: max-age=31536000`, when the server serves
+///   TLS
+///
+/// ```no_run
+/// use rustful::rest_server::RestServer;
+/// use rustful::security_headers::SecurityHeaders;
+///
+/// let mut svr = RestServer::new("api", "127.0.0.1", 8080).unwrap();
+/// svr.enable_security_headers(
+///     SecurityHeaders::new().content_security_policy(Some("default-src 'self' cdn.example.com")),
+/// );
+/// ```
+///
+/// Each header can be changed or turned off with `None`, and a header the
+/// handler set itself is left alone.
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    content_type_options: Option<String>,
+    frame_options: Option<String>,
+    content_security_policy: Option<String>,
+    hsts: Hsts,
+}
+
+impl SecurityHeaders {
+    /// Creates the middleware with the default headers.
+    pub fn new() -> Self {
+        return SecurityHeaders {
+            content_type_options: Some("nosniff".to_string()),
+            frame_options: Some("DENY".to_string()),
+            content_security_policy: Some("default-src 'self'".to_string()),
+            hsts: Hsts::Auto,
+        };
+    }
+
+    /// Sets `X-Content-Type-Options`. Defaults to `nosniff`.
+    pub fn content_type_options(mut self, value: Option<&str>) -> Self {
+        self.content_type_options = value.map(|v| v.to_string());
+        return self;
+    }
+
+    /// Sets `X-Frame-Options`. Defaults to `DENY`.
+    pub fn frame_options(mut self, value: Option<&str>) -> Self {
+        self.frame_options = value.map(|v| v.to_string());
+        return self;
+    }
+
+    /// Sets `Content-Security-Policy`. Defaults to `default-src 'self'`.
+    pub fn content_security_policy(mut self, value: Option<&str>) -> Self {
+        self.content_security_policy = value.map(|v| v.to_string());
+        return self;
+    }
+
+    /// Sets `Strict-Transport-Security`, whether or not the server serves
+    /// TLS. By default it is `max-age=31536000` with TLS, and not sent
+    /// without.
+    pub fn strict_transport_security(mut self, value: Option<&str>) -> Self {
+        self.hsts = Hsts::Set(value.map(|v| v.to_string()));
+        return self;
+    }
+
+    /// Settles the default `Strict-Transport-Security` once it is known
+    /// whether the server serves TLS.
+    pub(crate) fn resolve_hsts(mut self, tls: bool) -> Self {
+        if let Hsts::Auto = self.hsts {
+            self.hsts = Hsts::Set(tls.then(|| DEFAULT_HSTS.to_string()));
+        }
+        return self;
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        return SecurityHeaders::new();
+    }
+}
+
+impl Middleware for SecurityHeaders {
+    fn handle(&self, req: HttpRequest, next: Next) -> Result<HttpResponse, ServerError> {
+        // Errors get the headers too, once the server has answered them
+        let mut resp = next(req).unwrap_or_else(HttpResponse::from_error);
+        let hsts = match &self.hsts {
+            Hsts::Set(value) => value.as_deref(),
+            Hsts::Auto => None,
+        };
+        let headers = [
+            (
+                "X-Content-Type-Options",
+                self.content_type_options.as_deref(),
+            ),
+            ("X-Frame-Options", self.frame_options.as_deref()),
+            (
+                "Content-Security-Policy",
+                self.content_security_policy.as_deref(),
+            ),
+            ("Strict-Transport-Security", hsts),
+        ];
+        for (name, value) in headers {
+            if let Some(value) = value {
+                if resp.header(name).is_none() {
+                    resp.set_header(name, value);
+                }
+            }
+        }
+        return Ok(resp);
+    }
+}
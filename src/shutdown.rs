@@ -0,0 +1,86 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// State shared between a listening `RestServer` and its shutdown handles.
+pub(crate) struct ShutdownState {
+    requested: AtomicBool,
+    local_addrs: Mutex<Vec<SocketAddr>>,
+}
+
+impl ShutdownState {
+    pub(crate) fn new() -> Arc<Self> {
+        return Arc::new(ShutdownState {
+            requested: AtomicBool::new(false),
+            local_addrs: Mutex::new(Vec::new()),
+        });
+    }
+
+    pub(crate) fn is_requested(&self) -> bool {
+        return self.requested.load(Ordering::SeqCst);
+    }
+
+    /// Returns true while the server is bound and accepting connections,
+    /// i.e. `listen` is running and shutdown has not been requested.
+    pub(crate) fn is_accepting(&self) -> bool {
+        return !self.is_requested() && !self.local_addrs.lock().unwrap().is_empty();
+    }
+
+    /// Records the addresses the accept loops are blocked on (or clears them
+    /// once the loops have stopped), so `shutdown` knows where to connect.
+    pub(crate) fn set_local_addrs(&self, addrs: Vec<SocketAddr>) {
+        *self.local_addrs.lock().unwrap() = addrs;
+    }
+
+    pub(crate) fn local_addrs(&self) -> Vec<SocketAddr> {
+        return self.local_addrs.lock().unwrap().clone();
+    }
+}
+
+/// ShutdownHandle stops a listening `RestServer` from another thread.
+///
+/// Obtain one with `RestServer::shutdown_handle` before calling `listen`.
+/// Once shut down, a server stays shut down: a later `listen` call returns
+/// right after binding.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    state: Arc<ShutdownState>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(state: Arc<ShutdownState>) -> Self {
+        return ShutdownHandle { state };
+    }
+
+    /// Asks the server to stop accepting connections. `listen` returns once
+    /// the requests already being handled have finished.
+    pub fn shutdown(&self) {
+        self.state.requested.store(true, Ordering::SeqCst);
+
+        // Each accept loop is blocked in `incoming()`, so wake it up with a
+        // throwaway connection; it sees the flag and stops.
+        let addrs = self.state.local_addrs();
+        for addr in addrs {
+            let _ = TcpStream::connect(wake_addr(addr));
+        }
+    }
+
+    /// Returns true once `shutdown` has been called.
+    pub fn is_shutdown(&self) -> bool {
+        return self.state.is_requested();
+    }
+}
+
+/// Maps a wildcard bind address to the loopback address it can be reached on.
+fn wake_addr(addr: SocketAddr) -> SocketAddr {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    return SocketAddr::new(ip, addr.port());
+}
@@ -0,0 +1,27 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::Arc,
+};
+
+/// Application state registered with `RestServer::add_state`, holding at
+/// most one value per type.
+#[derive(Clone, Default)]
+pub(crate) struct StateMap {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl StateMap {
+    /// Stores `value`, replacing any earlier value of the same type.
+    pub(crate) fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        let _ = self.values.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Returns the value of type `T`, if one was stored.
+    pub(crate) fn get<T: 'static>(&self) -> Option<&T> {
+        return self
+            .values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>());
+    }
+}
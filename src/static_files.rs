@@ -0,0 +1,142 @@
+use std::{
+    fs::File,
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::date::http_date;
+use crate::error::ServerError;
+use crate::mime::mime_for_extension;
+use crate::rest_server::{HttpRequest, HttpResponse};
+use crate::url::percent_decode_strict;
+
+/// StaticDir serves files under `fs_root` for request paths under
+/// `url_prefix`.
+pub(crate) struct StaticDir {
+    url_prefix: String,
+    fs_root: PathBuf,
+    index_file: Option<String>,
+}
+
+impl StaticDir {
+    pub(crate) fn new(url_prefix: &str, fs_root: &str, index_file: Option<&str>) -> Self {
+        return StaticDir {
+            url_prefix: url_prefix.trim_end_matches('/').to_string(),
+            fs_root: PathBuf::from(fs_root),
+            index_file: index_file.map(|f| f.to_string()),
+        };
+    }
+
+    /// Responds with the file the request path points to, or `404 Not Found`.
+    pub(crate) fn serve(&self, req: HttpRequest) -> Result<HttpResponse, ServerError> {
+        let rel = req.path().strip_prefix(&self.url_prefix).unwrap_or("");
+        let mut path = match resolve(&self.fs_root, rel) {
+            Some(path) => path,
+            None => return Ok(HttpResponse::new(404, "Not Found")),
+        };
+        if path.is_dir() {
+            match &self.index_file {
+                Some(index) => path.push(index),
+                None => return Ok(HttpResponse::new(404, "Not Found")),
+            }
+        }
+
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Ok(HttpResponse::new(404, "Not Found"))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let metadata = file.metadata()?;
+        let len = usize::try_from(metadata.len()).unwrap_or(usize::MAX);
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let mut resp = match req.header("range").and_then(|v| parse_range(v, len)) {
+            Some(Ok((start, end))) => {
+                // Only the requested bytes are read, however large the file
+                let mut bytes = vec![0; end - start + 1];
+                let _ = file.seek(SeekFrom::Start(start as u64))?;
+                file.read_exact(&mut bytes)?;
+                let mut resp = HttpResponse::new(206, bytes);
+                resp.set_header("Content-Range", &format!("bytes {start}-{end}/{len}"));
+                resp
+            }
+            Some(Err(())) => {
+                let mut resp = HttpResponse::new(416, "Range Not Satisfiable");
+                resp.set_header("Content-Range", &format!("bytes */{len}"));
+                return Ok(resp);
+            }
+            None => {
+                let mut bytes = Vec::with_capacity(len);
+                let _ = file.read_to_end(&mut bytes)?;
+                HttpResponse::ok(bytes)
+            }
+        };
+        resp.set_header("Content-Type", mime_for_extension(ext));
+        resp.set_header("Accept-Ranges", "bytes");
+        // Lets clients revalidate with If-Modified-Since
+        if let Ok(modified) = metadata.modified() {
+            resp.set_header("Last-Modified", &http_date(modified));
+        }
+        return Ok(resp);
+    }
+}
+
+/// Maps the part of the request path below the URL prefix onto the file
+/// system root. Returns `None` if any segment could escape the root, such as
+/// `..`, or hides a separator behind an escape like `%2F`.
+fn resolve(root: &Path, rel: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for segment in rel.split('/').filter(|s| !s.is_empty()) {
+        let segment = percent_decode_strict(segment)?;
+        if segment == "." || segment == ".." || segment.contains(['/', '\\', '\0']) {
+            return None;
+        }
+        path.push(segment.as_ref());
+    }
+    return Some(path);
+}
+
+/// Parses a `Range` header for a file of `len` bytes into the inclusive
+/// byte positions to send. Returns `None` to send the whole file, for units
+/// other than bytes and for several ranges, which are not supported, and
+/// `Some(Err(()))` if the range is malformed or lies outside the file.
+fn parse_range(value: &str, len: usize) -> Option<Result<(usize, usize), ()>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = match spec.split_once('-') {
+        Some(parts) => parts,
+        None => return Some(Err(())),
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let parse = |n: &str| -> Result<usize, ()> {
+        if n.is_empty() || !n.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(());
+        }
+        // Positions too large for usize are past the end of any file
+        return Ok(n.parse::<usize>().unwrap_or(usize::MAX));
+    };
+    let range = if first.is_empty() {
+        // `-500` is the last 500 bytes
+        parse(last).and_then(|n| {
+            if n == 0 || len == 0 {
+                return Err(());
+            }
+            return Ok((len.saturating_sub(n), len - 1));
+        })
+    } else {
+        parse(first).and_then(|start| {
+            let end = match last {
+                "" => usize::MAX,
+                _ => parse(last)?,
+            };
+            if end < start || start >= len {
+                return Err(());
+            }
+            return Ok((start, end.min(len - 1)));
+        })
+    };
+    return Some(range);
+}
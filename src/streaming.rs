@@ -0,0 +1,223 @@
+use std::io::{BufRead, Error, ErrorKind, Read, Write};
+
+use crate::body::{read_chunk_end, read_chunk_size, read_trailers, TrailerLimits};
+
+/// Produces a response body incrementally by writing to the given writer.
+/// Each `write` call is sent to the client as one chunk, and `flush` pushes
+/// buffered chunks out to the socket.
+pub type BodyStream = Box<dyn FnOnce(&mut dyn Write) -> Result<(), Error> + Send>;
+
+/// ChunkedWriter frames everything written to it with the chunked transfer
+/// coding.
+pub(crate) struct ChunkedWriter<'a, W: Write> {
+    inner: &'a mut W,
+    written: usize,
+}
+
+impl<'a, W: Write> ChunkedWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W) -> Self {
+        return ChunkedWriter { inner, written: 0 };
+    }
+
+    /// Writes the terminating zero-size chunk and returns the number of body
+    /// bytes sent, excluding framing.
+    pub(crate) fn finish(self) -> Result<usize, Error> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()?;
+        return Ok(self.written);
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        // An empty chunk would end the body, so skip it
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.inner
+            .write_all(format!("{0:X}\r\n", buf.len()).as_bytes())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        self.written += buf.len();
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        return self.inner.flush();
+    }
+}
+
+/// CountingWriter passes writes through unframed, for HTTP/1.0 clients,
+/// counting the bytes written.
+pub(crate) struct CountingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    written: usize,
+}
+
+impl<'a, W: Write> CountingWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W) -> Self {
+        return CountingWriter { inner, written: 0 };
+    }
+
+    /// Flushes and returns the number of body bytes sent.
+    pub(crate) fn finish(self) -> Result<usize, Error> {
+        self.inner.flush()?;
+        return Ok(self.written);
+    }
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let n = self.inner.write(buf)?;
+        self.written += n;
+        return Ok(n);
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        return self.inner.flush();
+    }
+}
+
+/// EventSink sends Server-Sent Events to the client of a response created
+/// with `HttpResponse::event_stream`. Each event is flushed as soon as it is
+/// sent.
+pub struct EventSink<'a> {
+    writer: &'a mut dyn Write,
+}
+
+impl<'a> EventSink<'a> {
+    pub(crate) fn new(writer: &'a mut dyn Write) -> Self {
+        return EventSink { writer };
+    }
+
+    /// Sends an unnamed event, which browsers deliver as a `message` event.
+    /// Each line of `data` becomes its own `data:` field.
+    pub fn send(&mut self, data: &str) -> Result<(), Error> {
+        return self.write_event(None, None, data);
+    }
+
+    /// Sends an event with the given name, such as `update`.
+    pub fn send_event(&mut self, event: &str, data: &str) -> Result<(), Error> {
+        return self.write_event(Some(event), None, data);
+    }
+
+    /// Sends an event with a name and an ID, which a reconnecting client
+    /// sends back in `Last-Event-ID`.
+    pub fn send_with_id(&mut self, event: &str, id: &str, data: &str) -> Result<(), Error> {
+        return self.write_event(Some(event), Some(id), data);
+    }
+
+    /// Sends a comment line, which clients ignore. Sending one now and then
+    /// keeps idle connections from being closed by proxies.
+    pub fn comment(&mut self, text: &str) -> Result<(), Error> {
+        let mut out = String::new();
+        for line in text.lines() {
+            out.push_str(&format!(": {line}\n"));
+        }
+        out.push('\n');
+        self.writer.write_all(out.as_bytes())?;
+        return self.writer.flush();
+    }
+
+    fn write_event(
+        &mut self,
+        event: Option<&str>,
+        id: Option<&str>,
+        data: &str,
+    ) -> Result<(), Error> {
+        let mut out = String::new();
+        for (field, value) in [("event", event), ("id", id)] {
+            if let Some(value) = value {
+                if value.contains(['\r', '\n']) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("event {field} must not contain a line break: {value:?}"),
+                    ));
+                }
+                out.push_str(&format!("{field}: {value}\n"));
+            }
+        }
+        // Clients split data on any line ending, so normalize them
+        for line in data.replace("\r\n", "\n").split(['\r', '\n']) {
+            out.push_str(&format!("data: {line}\n"));
+        }
+        out.push('\n');
+        self.writer.write_all(out.as_bytes())?;
+        return self.writer.flush();
+    }
+}
+
+/// BodyReader reads the body of a request straight from the connection, for
+/// handlers registered with `RestServer::register_streaming`. It decodes the
+/// chunked transfer coding if the client used it, and otherwise stops after
+/// `Content-Length` bytes.
+pub struct BodyReader<'a> {
+    reader: &'a mut dyn BufRead,
+    content_length: Option<usize>,
+    /// Bytes left in the body, or in the current chunk of a chunked body.
+    remaining: usize,
+    /// Whether the last chunk of a chunked body has been read.
+    done: bool,
+    trailer_limits: TrailerLimits,
+}
+
+impl<'a> BodyReader<'a> {
+    /// A reader for a body of `content_length` bytes, or a chunked body if
+    /// `None`, whose trailer fields are held to `trailer_limits`.
+    pub(crate) fn new(
+        reader: &'a mut dyn BufRead,
+        content_length: Option<usize>,
+        trailer_limits: TrailerLimits,
+    ) -> Self {
+        return BodyReader {
+            reader,
+            content_length,
+            remaining: content_length.unwrap_or(0),
+            done: content_length.is_some(),
+            trailer_limits,
+        };
+    }
+
+    /// Returns the length the client declared for the body, or `None` for a
+    /// chunked body, whose length is only known once it has been read.
+    pub fn content_length(&self) -> Option<usize> {
+        return self.content_length;
+    }
+
+    /// Whether the whole body has been read, so the connection is positioned
+    /// at the start of the next request.
+    pub(crate) fn is_finished(&self) -> bool {
+        return self.done && self.remaining == 0;
+    }
+}
+
+impl Read for BodyReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() || self.is_finished() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            // At the start of the next chunk of a chunked body
+            let size = read_chunk_size(&mut self.reader)?;
+            if size == 0 {
+                read_trailers(&mut self.reader, self.trailer_limits)?;
+                self.done = true;
+                return Ok(0);
+            }
+            self.remaining = size;
+        }
+        let max = buf.len().min(self.remaining);
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "connection closed before the end of the body",
+            ));
+        }
+        self.remaining -= n;
+        if self.remaining == 0 && !self.done {
+            read_chunk_end(&mut self.reader)?;
+        }
+        return Ok(n);
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread::Scope,
+};
+
+type Job<'scope> = Box<dyn FnOnce() + Send + 'scope>;
+
+/// ThreadPool runs jobs on a fixed number of worker threads.
+///
+/// Workers are spawned inside a `thread::scope`, so jobs may borrow from the
+/// enclosing stack frame (e.g. the `RestServer` that is listening). Dropping
+/// the pool closes the job queue; the scope then waits for every worker to
+/// finish the jobs it already picked up.
+pub(crate) struct ThreadPool<'scope> {
+    sender: Option<mpsc::Sender<Job<'scope>>>,
+}
+
+impl<'scope> ThreadPool<'scope> {
+    /// Spawns `size` workers in the given scope. A size of zero is treated
+    /// as one.
+    pub(crate) fn new<'env>(scope: &'scope Scope<'scope, 'env>, size: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job<'scope>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..size.max(1) {
+            let receiver = Arc::clone(&receiver);
+            let _ = scope.spawn(move || loop {
+                // Hold the lock only while waiting for the next job
+                let job = match receiver.lock() {
+                    Ok(r) => r.recv(),
+                    Err(_) => return,
+                };
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            });
+        }
+        return ThreadPool {
+            sender: Some(sender),
+        };
+    }
+
+    /// Queues a job to be run by the next idle worker.
+    pub(crate) fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(Box::new(f));
+        }
+    }
+}
+
+impl Drop for ThreadPool<'_> {
+    fn drop(&mut self) {
+        // Closing the channel makes idle workers return from `recv`
+        drop(self.sender.take());
+    }
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    ServerConfig,
+};
+
+use crate::error::ServerError;
+
+/// Builds a TLS server configuration from a PEM certificate chain and a PEM
+/// private key (PKCS#8, PKCS#1 or SEC1).
+pub(crate) fn load_config(
+    cert_path: &str,
+    key_path: &str,
+) -> Result<Arc<ServerConfig>, ServerError> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| {
+            ServerError::InvalidConfig(format!("TLS certificate [{cert_path}]: {err}"))
+        })?;
+    if certs.is_empty() {
+        return Err(ServerError::InvalidConfig(format!(
+            "TLS certificate [{cert_path}]: no certificates found"
+        )));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|err| ServerError::InvalidConfig(format!("TLS key [{key_path}]: {err}")))?;
+
+    let config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|err| ServerError::InvalidConfig(format!("TLS config: {err}")))?;
+    return Ok(Arc::new(config));
+}
@@ -0,0 +1,158 @@
+use std::borrow::Cow;
+
+/// Decodes `%XX` escapes. Invalid escapes such as `%2` or `%zz` are left as
+/// they are, and bytes that do not form valid UTF-8 are replaced with
+/// U+FFFD. Returns the input unchanged, without allocating, if it has no
+/// escapes.
+pub fn percent_decode(s: &str) -> Cow<'_, str> {
+    return decode(s, false, false).unwrap_or(Cow::Borrowed(s));
+}
+
+/// Like `percent_decode`, but also decodes `+` as a space, as used in query
+/// strings and form bodies.
+pub fn form_decode(s: &str) -> Cow<'_, str> {
+    return decode(s, true, false).unwrap_or(Cow::Borrowed(s));
+}
+
+/// Strict `percent_decode`: returns `None` for invalid escapes or a result
+/// that is not valid UTF-8.
+pub(crate) fn percent_decode_strict(s: &str) -> Option<Cow<'_, str>> {
+    return decode(s, false, true);
+}
+
+/// Normalizes a request path for routing: decodes percent-escapes, drops
+/// empty and `.` segments and resolves `..` segments. A decoded `%` or `/`
+/// stays escaped as `%25` or `%2F`, so segment boundaries do not move and
+/// decoding the result again yields each segment's text exactly. A trailing
+/// slash is kept. Returns `None` if an escape is invalid, decodes to a
+/// control character, or the path climbs above the root, e.g.
+/// `/a/../../etc`.
+pub(crate) fn normalize_path(path: &str) -> Option<String> {
+    let mut segments: Vec<String> = Vec::new();
+    let mut trailing_slash = false;
+    for raw in path.split('/').skip(1) {
+        let segment = percent_decode_strict(raw)?;
+        if segment.contains(|c: char| c.is_control()) {
+            return None;
+        }
+        trailing_slash = matches!(segment.as_ref(), "" | "." | "..");
+        match segment.as_ref() {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            text => segments.push(text.replace('%', "%25").replace('/', "%2F")),
+        }
+    }
+    let mut normalized = format!("/{0}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        normalized.push('/');
+    }
+    return Some(normalized);
+}
+
+/// Escapes the bytes of a normalized path that may not appear in a URL, such
+/// as spaces and non-ASCII text, for use in a `Location` header. Existing
+/// escapes are kept.
+pub(crate) fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_graphic() && !matches!(b, b'?' | b'#' | b'"' | b'<' | b'>' | b'\\') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    return encoded;
+}
+
+/// Encodes every byte except the unreserved characters `A-Z a-z 0-9 - . _ ~`
+/// as `%XX`, making `s` safe to use in any part of a URL.
+pub fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    return encoded;
+}
+
+/// Decodes escapes, and `+` if `plus` is set. In `strict` mode invalid
+/// escapes and invalid UTF-8 make it return `None`; otherwise it always
+/// returns `Some`.
+fn decode(s: &str, plus: bool, strict: bool) -> Option<Cow<'_, str>> {
+    let escaped = s.contains('%') || (plus && s.contains('+'));
+    if !escaped {
+        return Some(Cow::Borrowed(s));
+    }
+    let bytes = s.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' if plus => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len()
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit() =>
+            {
+                decoded.push(hex_value(bytes[i + 1]) << 4 | hex_value(bytes[i + 2]));
+                i += 2;
+            }
+            b'%' if strict => return None,
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    if strict {
+        return String::from_utf8(decoded).ok().map(Cow::Owned);
+    }
+    return Some(Cow::Owned(String::from_utf8_lossy(&decoded).into_owned()));
+}
+
+fn hex_value(b: u8) -> u8 {
+    return match b {
+        b'0'..=b'9' => b - b'0',
+        b'a'..=b'f' => b - b'a' + 10,
+        _ => b - b'A' + 10,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{form_decode, normalize_path, percent_decode, percent_decode_strict};
+
+    #[test]
+    fn leaves_invalid_escapes_alone() {
+        assert_eq!(percent_decode("a%2"), "a%2");
+        assert_eq!(percent_decode("%2"), "%2");
+        assert_eq!(percent_decode("%zz"), "%zz");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode_strict("a%2"), None);
+        assert_eq!(percent_decode_strict("%zz"), None);
+    }
+
+    #[test]
+    fn decodes_multi_byte_utf8() {
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode_strict("caf%c3%a9").as_deref(), Some("café"));
+    }
+
+    #[test]
+    fn handles_invalid_utf8() {
+        assert_eq!(percent_decode("a%FFb"), "a\u{FFFD}b");
+        // Half of a two-byte sequence is not valid either
+        assert_eq!(percent_decode("%C3"), "\u{FFFD}");
+        assert_eq!(percent_decode_strict("a%FFb"), None);
+        assert_eq!(normalize_path("/a%FF"), None);
+    }
+
+    #[test]
+    fn decodes_plus_only_in_forms() {
+        assert_eq!(form_decode("a+b%2Bc"), "a b+c");
+        assert_eq!(percent_decode("a+b"), "a+b");
+        assert_eq!(normalize_path("/a+b/c%20d").as_deref(), Some("/a+b/c d"));
+    }
+}
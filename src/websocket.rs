@@ -0,0 +1,331 @@
+use std::{
+    io::{BufReader, Error, ErrorKind, Read, Write},
+    sync::Arc,
+};
+
+use crate::base64;
+use crate::rest_server::{has_token, HttpRequest, HttpResponse, HttpVersion};
+use crate::sha1::sha1;
+
+/// Appended to the client's key to compute `Sec-WebSocket-Accept`.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Close status codes, see RFC 6455 section 7.4.1.
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_INVALID_DATA: u16 = 1007;
+const CLOSE_TOO_BIG: u16 = 1009;
+
+/// A byte stream an upgraded connection reads and writes.
+pub(crate) trait Duplex: Read + Write {}
+
+impl<T: Read + Write> Duplex for T {}
+
+/// Reads an upgraded connection through the reader that has been buffering
+/// it, so bytes the client sent right after the handshake are not lost, and
+/// writes to it directly.
+pub(crate) struct Upgraded<'a, S: Read + Write>(pub(crate) &'a mut BufReader<S>);
+
+impl<S: Read + Write> Read for Upgraded<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        return self.0.read(buf);
+    }
+}
+
+impl<S: Read + Write> Write for Upgraded<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        return self.0.get_mut().write(buf);
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        return self.0.get_mut().flush();
+    }
+}
+
+/// Takes over a connection once its `101 Switching Protocols` head has been
+/// sent.
+pub(crate) type Upgrade = Box<dyn FnOnce(&mut dyn Duplex) -> Result<(), Error> + Send>;
+
+/// A handler for a WebSocket route, called once the handshake is done with
+/// the upgrade request and the open socket.
+pub(crate) type SocketHandler =
+    Arc<dyn Fn(HttpRequest, &mut WebSocket) -> Result<(), Error> + Send + Sync>;
+
+/// Message is one complete WebSocket message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// The close status code and reason, if the peer sent one.
+    Close(Option<(u16, String)>),
+}
+
+/// WebSocket is an open WebSocket connection, handed to handlers registered
+/// with `RestServer::register_websocket`.
+///
+/// Pings are answered automatically. A close from the peer is answered, and
+/// a socket dropped without closing sends a normal close.
+pub struct WebSocket<'a> {
+    conn: &'a mut dyn Duplex,
+    max_message_size: usize,
+    /// Opcode and data of a fragmented message being received. Control
+    /// frames may arrive between its fragments.
+    fragments: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+}
+
+impl<'a> WebSocket<'a> {
+    pub(crate) fn new(conn: &'a mut dyn Duplex, max_message_size: usize) -> Self {
+        return WebSocket {
+            conn,
+            max_message_size,
+            fragments: None,
+            close_sent: false,
+        };
+    }
+
+    /// Waits for the next message. Fragmented messages are reassembled.
+    /// After `Message::Close` the connection is finished and further reads
+    /// fail. Protocol violations by the peer close the connection and
+    /// return an `InvalidData` error.
+    pub fn read_message(&mut self) -> Result<Message, Error> {
+        loop {
+            let (fin, opcode, payload) = self.read_frame()?;
+            match opcode {
+                OP_PING => {
+                    self.write_frame(OP_PONG, &payload)?;
+                    return Ok(Message::Ping(payload));
+                }
+                OP_PONG => return Ok(Message::Pong(payload)),
+                OP_CLOSE => return self.answer_close(&payload),
+                OP_TEXT | OP_BINARY if self.fragments.is_none() => {
+                    if fin {
+                        return self.complete(opcode, payload);
+                    }
+                    self.fragments = Some((opcode, payload));
+                }
+                OP_CONTINUATION if self.fragments.is_some() => {
+                    let (first, mut data) = self.fragments.take().unwrap_or_default();
+                    if data.len() + payload.len() > self.max_message_size {
+                        return Err(self.fail(CLOSE_TOO_BIG, "message too large"));
+                    }
+                    data.extend_from_slice(&payload);
+                    if fin {
+                        return self.complete(first, data);
+                    }
+                    self.fragments = Some((first, data));
+                }
+                _ => return Err(self.fail(CLOSE_PROTOCOL_ERROR, "unexpected frame")),
+            }
+        }
+    }
+
+    /// Sends a message.
+    pub fn send(&mut self, message: Message) -> Result<(), Error> {
+        return match message {
+            Message::Text(text) => self.write_frame(OP_TEXT, text.as_bytes()),
+            Message::Binary(data) => self.write_frame(OP_BINARY, &data),
+            Message::Ping(data) => self.write_frame(OP_PING, &data),
+            Message::Pong(data) => self.write_frame(OP_PONG, &data),
+            Message::Close(status) => {
+                let (code, reason) = status.unwrap_or((CLOSE_NORMAL, String::new()));
+                self.close(code, &reason)
+            }
+        };
+    }
+
+    /// Sends a text message.
+    pub fn send_text(&mut self, text: &str) -> Result<(), Error> {
+        return self.write_frame(OP_TEXT, text.as_bytes());
+    }
+
+    /// Sends a binary message.
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<(), Error> {
+        return self.write_frame(OP_BINARY, data);
+    }
+
+    /// Sends a close frame with the given status code and reason. The peer
+    /// answers with a close of its own, which `read_message` returns.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), Error> {
+        if self.close_sent {
+            return Ok(());
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        // Control frames carry at most 125 bytes
+        payload.truncate(125);
+        self.close_sent = true;
+        return self.write_frame(OP_CLOSE, &payload);
+    }
+
+    /// Turns the payload of a final data frame into a message.
+    fn complete(&mut self, opcode: u8, payload: Vec<u8>) -> Result<Message, Error> {
+        if opcode == OP_BINARY {
+            return Ok(Message::Binary(payload));
+        }
+        return match String::from_utf8(payload) {
+            Ok(text) => Ok(Message::Text(text)),
+            Err(_) => Err(self.fail(CLOSE_INVALID_DATA, "text message is not valid UTF-8")),
+        };
+    }
+
+    /// Echoes a close frame from the peer and reports it as a message.
+    fn answer_close(&mut self, payload: &[u8]) -> Result<Message, Error> {
+        let status = match payload {
+            [] => None,
+            [hi, lo, reason @ ..] => {
+                let reason = String::from_utf8_lossy(reason).into_owned();
+                Some((u16::from_be_bytes([*hi, *lo]), reason))
+            }
+            _ => return Err(self.fail(CLOSE_PROTOCOL_ERROR, "malformed close frame")),
+        };
+        let code = status.as_ref().map_or(CLOSE_NORMAL, |(code, _)| *code);
+        self.close(code, "")?;
+        return Ok(Message::Close(status));
+    }
+
+    /// Closes the connection because of a protocol violation and returns
+    /// the error to report.
+    fn fail(&mut self, code: u16, reason: &str) -> Error {
+        let _ = self.close(code, reason);
+        return Error::new(ErrorKind::InvalidData, format!("WebSocket: {reason}"));
+    }
+
+    /// Reads one frame and returns its FIN bit, opcode and unmasked payload.
+    fn read_frame(&mut self) -> Result<(bool, u8, Vec<u8>), Error> {
+        let mut head = [0u8; 2];
+        self.conn.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        if head[0] & 0x70 != 0 {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "unexpected reserved bits"));
+        }
+        // Clients must mask every frame they send
+        if head[1] & 0x80 == 0 {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "frame is not masked"));
+        }
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut ext = [0u8; 2];
+                self.conn.read_exact(&mut ext)?;
+                u64::from(u16::from_be_bytes(ext))
+            }
+            127 => {
+                let mut ext = [0u8; 8];
+                self.conn.read_exact(&mut ext)?;
+                u64::from_be_bytes(ext)
+            }
+            n => u64::from(n),
+        };
+        let is_control = opcode & 0x8 != 0;
+        if is_control && (!fin || len > 125) {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "invalid control frame"));
+        }
+        if len > self.max_message_size as u64 {
+            return Err(self.fail(CLOSE_TOO_BIG, "message too large"));
+        }
+
+        let mut mask = [0u8; 4];
+        self.conn.read_exact(&mut mask)?;
+        let mut payload = vec![0u8; len as usize];
+        self.conn.read_exact(&mut payload)?;
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+        return Ok((fin, opcode, payload));
+    }
+
+    /// Writes one unfragmented, unmasked frame and flushes it.
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), Error> {
+        let mut frame: Vec<u8> = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            n if n < 126 => frame.push(n as u8),
+            n if n <= usize::from(u16::MAX) => {
+                frame.push(126);
+                frame.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                frame.push(127);
+                frame.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.conn.write_all(&frame)?;
+        return self.conn.flush();
+    }
+}
+
+impl Drop for WebSocket<'_> {
+    fn drop(&mut self) {
+        let _ = self.close(CLOSE_NORMAL, "");
+    }
+}
+
+/// Answers a WebSocket upgrade request: `101 Switching Protocols` with an
+/// upgrade that runs `handler` on the connection, or an error response if
+/// the request is not a valid handshake.
+pub(crate) fn handshake(
+    req: HttpRequest,
+    handler: &SocketHandler,
+    max_message_size: usize,
+) -> HttpResponse {
+    let is_upgrade = req.version() == HttpVersion::Http11
+        && req
+            .header("upgrade")
+            .is_some_and(|v| has_token(v, "websocket"))
+        && req
+            .header("connection")
+            .is_some_and(|v| has_token(v, "upgrade"));
+    if !is_upgrade || req.header("sec-websocket-version") != Some("13") {
+        let mut resp = HttpResponse::new(426, "Upgrade Required: expected a WebSocket handshake");
+        resp.set_header("Upgrade", "websocket");
+        resp.set_header("Sec-WebSocket-Version", "13");
+        return resp;
+    }
+    let key = match req.header("sec-websocket-key") {
+        Some(key) if base64::decode(key.trim()).is_some_and(|k| k.len() == 16) => {
+            key.trim().to_string()
+        }
+        _ => return HttpResponse::new(400, "Bad Request: invalid Sec-WebSocket-Key"),
+    };
+
+    let mut resp = HttpResponse::new(101, "");
+    resp.set_header("Upgrade", "websocket");
+    resp.set_header("Connection", "Upgrade");
+    resp.set_header("Sec-WebSocket-Accept", &accept_key(&key));
+    let handler = Arc::clone(handler);
+    resp.set_upgrade(Box::new(move |conn| {
+        let mut socket = WebSocket::new(conn, max_message_size);
+        return handler(req, &mut socket);
+    }));
+    return resp;
+}
+
+/// Computes `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`.
+fn accept_key(key: &str) -> String {
+    return base64::encode(&sha1(format!("{key}{ACCEPT_GUID}").as_bytes()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::accept_key;
+
+    #[test]
+    fn computes_the_rfc_6455_accept_key() {
+        // RFC 6455, section 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+}
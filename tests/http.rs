@@ -0,0 +1,575 @@
+//! Tests that feed raw requests to `RestServer::handle_for_test`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rustful::error::ServerError;
+use rustful::rest_server::{HttpMethod, HttpResponse, RestServer, TrailingSlash};
+
+fn test_server() -> RestServer {
+    let mut svr = RestServer::new("test", "127.0.0.1", 8080).unwrap();
+    svr.set_access_logger(|_| {});
+    return svr;
+}
+
+/// Splits a raw response into its head and body.
+fn split_response(raw: &[u8]) -> (String, &[u8]) {
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("response has no end of head");
+    let head = String::from_utf8(raw[..end].to_vec()).unwrap();
+    return (head, &raw[end + 4..]);
+}
+
+/// Decodes a chunked body, checking that it ends with the last chunk and
+/// nothing follows it. Returns the data and the number of chunks.
+fn dechunk(mut body: &[u8]) -> (Vec<u8>, usize) {
+    let mut data: Vec<u8> = Vec::new();
+    let mut chunks = 0;
+    loop {
+        let line_end = body.windows(2).position(|w| w == b"\r\n").unwrap();
+        let size_line = std::str::from_utf8(&body[..line_end]).unwrap();
+        let size = usize::from_str_radix(size_line, 16).unwrap();
+        body = &body[line_end + 2..];
+        if size == 0 {
+            assert_eq!(body, b"\r\n", "the last chunk is not followed by CRLF");
+            return (data, chunks);
+        }
+        data.extend_from_slice(&body[..size]);
+        assert_eq!(&body[size..size + 2], b"\r\n");
+        body = &body[size + 2..];
+        chunks += 1;
+    }
+}
+
+#[test]
+fn streams_a_chunked_body() {
+    let mut svr = test_server();
+    svr.register_path("/stream", |_req| {
+        return Ok(HttpResponse::stream(|writer| {
+            writer.write_all(b"first,")?;
+            writer.write_all(b"")?;
+            writer.write_all(b"second,")?;
+            writer.flush()?;
+            writer.write_all(&[0, 255, b'\r', b'\n'])?;
+            return Ok(());
+        }));
+    })
+    .unwrap();
+
+    let raw = svr.handle_for_test(b"GET /stream HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let (head, body) = split_response(&raw);
+    assert!(head.contains("\r\nTransfer-Encoding: chunked"), "{head}");
+    assert!(!head.contains("Content-Length"), "{head}");
+    assert!(body.ends_with(b"\r\n0\r\n\r\n"));
+    let (data, chunks) = dechunk(body);
+    assert_eq!(data, b"first,second,\x00\xff\r\n");
+    assert_eq!(chunks, 3);
+}
+
+#[test]
+fn decodes_plus_in_the_query_but_not_the_path() {
+    let mut svr = test_server();
+    svr.register_path("/echo/:word", |req| {
+        let word = req.param("word").unwrap_or("").to_string();
+        return Ok(format!("{word}|{0}", req.query("q").unwrap_or("")));
+    })
+    .unwrap();
+
+    let raw =
+        svr.handle_for_test(b"GET /echo/a+b%20c?q=a+b%20c HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let (head, body) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{head}");
+    assert_eq!(body, b"a+b c|a b c");
+}
+
+#[test]
+fn routes_by_regex() {
+    let mut svr = test_server();
+    svr.register_regex(HttpMethod::GET, r"/item/(\d+)", |req| {
+        return Ok(format!("item {0}", req.param("1").unwrap_or("")));
+    })
+    .unwrap();
+    let result = svr.register_regex(HttpMethod::GET, r"/broken/(\d+", |_req| Ok(""));
+    assert!(matches!(result, Err(ServerError::InvalidConfig(_))));
+
+    let raw = svr.handle_for_test(b"GET /item/7 HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert_eq!(split_response(&raw).1, b"item 7");
+    let raw = svr.handle_for_test(b"GET /item/x HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert!(split_response(&raw).0.starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+fn redirects_set_status_and_location() {
+    let cases = [
+        (HttpResponse::redirect_permanent("/moved"), 301),
+        (HttpResponse::redirect("/moved"), 302),
+        (HttpResponse::redirect_with_status(303, "/moved"), 303),
+        (HttpResponse::redirect_with_status(307, "/moved"), 307),
+        (HttpResponse::redirect_with_status(308, "/moved"), 308),
+    ];
+    for (resp, status) in cases {
+        let raw = resp.serialize();
+        assert_eq!(resp.status(), status);
+        assert_eq!(resp.header("location"), Some("/moved"));
+        assert!(resp.body().is_empty());
+        let (head, body) = split_response(&raw);
+        assert!(head.contains("\r\nLocation: /moved"), "{head}");
+        assert!(head.contains("\r\nContent-Length: 0"), "{head}");
+        assert!(body.is_empty());
+    }
+}
+
+#[test]
+fn redirects_to_the_path_without_a_trailing_slash() {
+    let mut svr = test_server();
+    svr.set_trailing_slash(TrailingSlash::Redirect);
+    svr.register_path("/docs", |_req| Ok("docs")).unwrap();
+    svr.register(HttpMethod::POST, "/docs", |_req| Ok("posted"))
+        .unwrap();
+
+    let raw = svr.handle_for_test(b"GET /docs/?page=2 HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let head = split_response(&raw).0;
+    assert!(head.starts_with("HTTP/1.1 301 Moved Permanently"), "{head}");
+    assert!(head.contains("\r\nLocation: /docs?page=2"), "{head}");
+
+    let raw = svr
+        .handle_for_test(b"POST /docs/ HTTP/1.1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+    let head = split_response(&raw).0;
+    assert!(
+        head.starts_with("HTTP/1.1 308 Permanent Redirect"),
+        "{head}"
+    );
+    assert!(head.contains("\r\nLocation: /docs"), "{head}");
+}
+
+#[test]
+fn answers_a_wrong_method_with_405() {
+    let mut svr = test_server();
+    svr.register_path("/ping", |_req| Ok("pong")).unwrap();
+
+    let raw = svr
+        .handle_for_test(b"POST /ping HTTP/1.1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+    let (head, body) = split_response(&raw);
+    assert!(
+        head.starts_with("HTTP/1.1 405 Method Not Allowed"),
+        "{head}"
+    );
+    assert!(head.contains("\r\nAllow: GET, HEAD, OPTIONS\r\n"), "{head}");
+    assert_eq!(body, b"Method Not Allowed");
+
+    let raw = svr.handle_for_test(b"OPTIONS /ping HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let head = split_response(&raw).0;
+    assert!(head.starts_with("HTTP/1.1 204 No Content"), "{head}");
+    assert!(head.contains("\r\nAllow: GET, HEAD, OPTIONS\r\n"), "{head}");
+}
+
+#[test]
+fn frames_server_sent_events() {
+    let mut svr = test_server();
+    svr.register_path("/events", |_req| {
+        return Ok(HttpResponse::event_stream(|sink| {
+            sink.send("a")?;
+            sink.send("b\nc")?;
+            sink.send_event("update", "d\r\ne")?;
+            sink.send_with_id("update", "7", "f")?;
+            sink.comment("keep-alive")?;
+            return Ok(());
+        }));
+    })
+    .unwrap();
+
+    let raw = svr.handle_for_test(b"GET /events HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let (head, body) = split_response(&raw);
+    assert!(
+        head.contains("\r\nContent-Type: text/event-stream"),
+        "{head}"
+    );
+    assert!(head.contains("\r\nCache-Control: no-cache"), "{head}");
+    let (data, chunks) = dechunk(body);
+    assert_eq!(
+        String::from_utf8(data).unwrap(),
+        "data: a\n\n\
+         data: b\ndata: c\n\n\
+         event: update\ndata: d\ndata: e\n\n\
+         event: update\nid: 7\ndata: f\n\n\
+         : keep-alive\n\n"
+    );
+    // Each event is flushed on its own
+    assert_eq!(chunks, 5);
+}
+
+#[test]
+fn rejects_a_header_value_with_a_line_break() {
+    let mut resp = HttpResponse::ok("");
+    resp.set_header("X-A", "a\r\nSet-Cookie: evil=1");
+    assert_eq!(resp.header("x-a"), None);
+}
+
+#[test]
+fn rejects_a_header_name_that_is_not_a_token() {
+    let mut resp = HttpResponse::ok("");
+    resp.set_header("X-A: b\r\nX-B", "1");
+    assert_eq!(resp.headers().count(), 0);
+}
+
+#[test]
+fn rejects_invalid_default_headers() {
+    let mut svr = test_server();
+    svr.set_default_header("X-Env", "prod").unwrap();
+    let result = svr.set_default_header("X-A", "a\r\nSet-Cookie: evil=1");
+    assert!(matches!(result, Err(ServerError::InvalidConfig(_))));
+    let raw = svr.handle_for_test(b"GET /missing HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let (head, _) = split_response(&raw);
+    assert!(head.contains("X-Env: prod"), "{head}");
+    assert!(!head.contains("evil"), "{head}");
+}
+
+#[test]
+fn answers_a_redirect_to_an_invalid_location_with_500() {
+    let mut svr = test_server();
+    svr.register_path("/go", |req| {
+        let to = req.query("to").unwrap_or("/");
+        return Ok(HttpResponse::redirect(to));
+    })
+    .unwrap();
+    svr.register_path("/ok", |_req| Ok("still here")).unwrap();
+
+    let raw = svr.handle_for_test(
+        b"GET /go?to=/a%0d%0aSet-Cookie:%20evil=1 HTTP/1.1\r\n\r\nGET /ok HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    let raw = String::from_utf8(raw).unwrap();
+    assert!(raw.starts_with("HTTP/1.1 500"), "{raw}");
+    assert!(
+        !raw.contains("\r\nSet-Cookie") && !raw.contains("\r\nLocation"),
+        "{raw}"
+    );
+    assert!(raw.ends_with("\r\n\r\nstill here"), "{raw}");
+}
+
+#[test]
+fn handler_cannot_inject_headers() {
+    let mut svr = test_server();
+    svr.register_path("/inject", |_req| {
+        let mut resp = HttpResponse::ok("hi");
+        resp.set_header("X-A", "a\r\nSet-Cookie: evil=1");
+        return Ok(resp);
+    })
+    .unwrap();
+
+    let raw = svr.handle_for_test(b"GET /inject HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let (head, _) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 500"), "{head}");
+    assert!(!head.contains("evil"), "{head}");
+}
+
+#[test]
+fn rejects_ambiguous_content_lengths() {
+    let mut svr = test_server();
+    svr.register(HttpMethod::POST, "/echo", |req| Ok(req.body().to_vec()))
+        .unwrap();
+
+    for headers in [
+        "Content-Length: +2\r\n",
+        "Content-Length: 2\r\nContent-Length: 3\r\n",
+        "Content-Length: 2, 3\r\n",
+    ] {
+        let request = format!("POST /echo HTTP/1.1\r\n{headers}\r\nhi");
+        let raw = svr.handle_for_test(request.as_bytes());
+        let (head, body) = split_response(&raw);
+        assert!(
+            head.starts_with("HTTP/1.1 400 Bad Request"),
+            "{headers}: {head}"
+        );
+        assert!(head.contains("\r\nConnection: close"), "{head}");
+        assert_eq!(body, b"Bad Request: invalid Content-Length");
+    }
+
+    // Copies of the same length are not ambiguous
+    let raw = svr.handle_for_test(
+        b"POST /echo HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 2\r\nConnection: close\r\n\r\nhi",
+    );
+    assert_eq!(split_response(&raw).1, b"hi");
+}
+
+#[test]
+fn rejects_unsupported_transfer_codings() {
+    let mut svr = test_server();
+    svr.register(HttpMethod::POST, "/echo", |req| Ok(req.body().to_vec()))
+        .unwrap();
+
+    for (coding, status) in [
+        ("gzip", "400 Bad Request"),
+        ("chunked, gzip", "400 Bad Request"),
+        ("chunked, chunked", "400 Bad Request"),
+        ("gzip, chunked", "501 Not Implemented"),
+    ] {
+        let request = format!(
+            "POST /echo HTTP/1.1\r\nTransfer-Encoding: {coding}\r\n\r\n2\r\nhi\r\n0\r\n\r\nGET /echo HTTP/1.1\r\n\r\n"
+        );
+        let raw = String::from_utf8(svr.handle_for_test(request.as_bytes())).unwrap();
+        assert!(
+            raw.starts_with(&format!("HTTP/1.1 {status}")),
+            "{coding}: {raw}"
+        );
+        assert!(raw.contains("\r\nConnection: close\r\n"), "{raw}");
+        // Nothing after the rejected request is read as another request
+        assert_eq!(raw.matches("HTTP/1.1 ").count(), 1, "{coding}: {raw}");
+    }
+}
+
+#[test]
+fn ignores_content_length_next_to_chunked() {
+    let mut svr = test_server();
+    svr.register(HttpMethod::POST, "/echo", |req| Ok(req.body().to_vec()))
+        .unwrap();
+
+    let raw = svr.handle_for_test(
+        b"POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n2\r\nhi\r\n0\r\n\r\nGET /echo HTTP/1.1\r\n\r\n",
+    );
+    let (head, body) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{head}");
+    assert!(head.contains("\r\nConnection: close"), "{head}");
+    assert_eq!(body, b"hi");
+}
+
+#[test]
+fn rejects_malformed_header_names() {
+    let mut svr = test_server();
+    svr.register(HttpMethod::POST, "/echo", |req| Ok(req.body().to_vec()))
+        .unwrap();
+
+    for header in [
+        "Content-Length : 5",
+        " Content-Length: 5",
+        ": 5",
+        "Content Length: 5",
+        "X-A: a\0b",
+    ] {
+        let request = format!("POST /echo HTTP/1.1\r\n{header}\r\n\r\nhello");
+        let raw = svr.handle_for_test(request.as_bytes());
+        let (head, body) = split_response(&raw);
+        assert!(
+            head.starts_with("HTTP/1.1 400 Bad Request"),
+            "{header:?}: {head}"
+        );
+        assert_eq!(body, b"Bad Request: malformed header line");
+    }
+}
+
+#[test]
+fn does_not_handle_a_request_cut_off_in_its_headers() {
+    let handled = Arc::new(AtomicBool::new(false));
+    let mut svr = test_server();
+    let flag = Arc::clone(&handled);
+    svr.register_path("/ping", move |_req| {
+        flag.store(true, Ordering::SeqCst);
+        return Ok("pong");
+    })
+    .unwrap();
+
+    for request in [
+        &b"GET /ping HTTP/1.1\r\nHost: a"[..],
+        b"GET /ping HTTP/1.1\r\nHost: a\r\n",
+        b"GET /ping HTTP/1.1\r\n",
+    ] {
+        let raw = svr.handle_for_test(request);
+        let (head, body) = split_response(&raw);
+        assert!(head.starts_with("HTTP/1.1 400 Bad Request"), "{head}");
+        assert_eq!(body, b"Bad Request: headers ended early");
+    }
+    // A request line cut off by the client hanging up gets no answer at all
+    assert!(svr.handle_for_test(b"GET /ping HT").is_empty());
+    assert!(!handled.load(Ordering::SeqCst));
+}
+
+#[test]
+fn limits_trailer_fields() {
+    let mut svr = test_server();
+    svr.set_max_headers(3);
+    svr.register(HttpMethod::POST, "/echo", |req| Ok(req.body().to_vec()))
+        .unwrap();
+
+    let raw = svr.handle_for_test(
+        b"POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n2\r\nhi\r\n0\r\nA: 1\r\nB: 2\r\n\r\n",
+    );
+    assert_eq!(split_response(&raw).1, b"hi");
+
+    let raw = svr.handle_for_test(
+        b"POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhi\r\n0\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\n\r\n",
+    );
+    let (head, body) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 400 Bad Request"), "{head}");
+    assert_eq!(
+        body,
+        b"Bad Request: chunked body has too many trailer fields"
+    );
+}
+
+#[test]
+fn serves_a_byte_range_of_a_file() {
+    let dir = std::env::temp_dir().join(format!("rustful-range-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("data.txt"), b"0123456789").unwrap();
+    let mut svr = test_server();
+    svr.serve_dir("/static", dir.to_str().unwrap()).unwrap();
+    // Middleware sees the partial body like any other
+    svr.use_middleware(
+        |req, next: rustful::middleware::Next| -> Result<HttpResponse, ServerError> {
+            let mut resp = next(req)?;
+            let seen = String::from_utf8_lossy(resp.body()).into_owned();
+            resp.set_header("X-Seen-Body", &seen);
+            return Ok(resp);
+        },
+    );
+
+    let raw = svr.handle_for_test(
+        b"GET /static/data.txt HTTP/1.1\r\nRange: bytes=2-5\r\nConnection: close\r\n\r\n",
+    );
+    let (head, body) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 206"), "{head}");
+    assert!(head.contains("Content-Range: bytes 2-5/10"), "{head}");
+    assert!(head.contains("X-Seen-Body: 2345"), "{head}");
+    assert_eq!(body, b"2345");
+
+    let raw = svr.handle_for_test(
+        b"GET /static/data.txt HTTP/1.1\r\nRange: bytes=-3\r\nConnection: close\r\n\r\n",
+    );
+    let (head, body) = split_response(&raw);
+    assert!(head.contains("Content-Range: bytes 7-9/10"), "{head}");
+    assert_eq!(body, b"789");
+
+    let raw = svr.handle_for_test(
+        b"GET /static/data.txt HTTP/1.1\r\nRange: bytes=10-\r\nConnection: close\r\n\r\n",
+    );
+    let (head, _) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 416"), "{head}");
+    assert!(head.contains("Content-Range: bytes */10"), "{head}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_clashing_proxy_adds_no_routes() {
+    let mut svr = test_server();
+    svr.register(HttpMethod::PATCH, "/api/*rest", |_req| Ok("patched"))
+        .unwrap();
+    let result = svr.proxy("/api", "http://127.0.0.1:9");
+    assert!(matches!(
+        result,
+        Err(ServerError::DuplicateRoute {
+            method: HttpMethod::PATCH,
+            ..
+        })
+    ));
+    assert_eq!(
+        svr.routes(),
+        vec![(HttpMethod::PATCH, "/api/*rest".to_string())]
+    );
+}
+
+#[test]
+fn logs_requests_answered_before_dispatch() {
+    let entries = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut svr = test_server();
+    let logged = Arc::clone(&entries);
+    svr.set_access_logger(move |entry| {
+        let path = entry.path.map(|p| p.to_string());
+        logged
+            .lock()
+            .unwrap()
+            .push((entry.method, path, entry.status));
+    });
+
+    svr.handle_for_test(b"NOT A REQUEST\r\n\r\n");
+    svr.handle_for_test(b"GET /upload HTTP/1.1\r\nContent-Length: ten\r\n\r\n");
+
+    assert_eq!(
+        *entries.lock().unwrap(),
+        vec![
+            (None, None, 400),
+            (Some(HttpMethod::GET), Some("/upload".to_string()), 400),
+        ]
+    );
+}
+
+#[test]
+fn adds_security_headers_to_errors() {
+    let mut svr = test_server();
+    svr.set_expose_errors(false);
+    svr.enable_security_headers(rustful::security_headers::SecurityHeaders::new());
+    svr.register_path("/fail", |_req| -> Result<String, ServerError> {
+        return Err(ServerError::HandlerError("broken".to_string()));
+    })
+    .unwrap();
+
+    let raw = svr.handle_for_test(b"GET /fail HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let (head, body) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 500"), "{head}");
+    assert!(head.contains("X-Content-Type-Options: nosniff"), "{head}");
+    assert_eq!(body, b"Internal Server Error");
+}
+
+#[test]
+fn adds_cors_headers_to_errors() {
+    let mut svr = test_server();
+    svr.use_middleware(rustful::cors::Cors::any());
+    svr.register_path("/fail", |_req| -> Result<String, ServerError> {
+        return Err(ServerError::BadRequest("no".to_string()));
+    })
+    .unwrap();
+
+    let raw = svr.handle_for_test(
+        b"GET /fail HTTP/1.1\r\nOrigin: http://example.com\r\nConnection: close\r\n\r\n",
+    );
+    let (head, _) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 400"), "{head}");
+    assert!(head.contains("Access-Control-Allow-Origin: *"), "{head}");
+}
+
+#[test]
+fn proxies_the_upstream_length_of_a_head_response() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let upstream = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = upstream.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = upstream.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            request.push(line);
+        }
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 42\r\n\r\n")
+            .unwrap();
+        return request;
+    });
+
+    let mut svr = test_server();
+    svr.proxy("/api", &format!("http://{addr}")).unwrap();
+    let raw = svr.handle_for_test(b"HEAD /api/file HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let (head, body) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{head}");
+    assert!(head.contains("Content-Length: 42"), "{head}");
+    assert!(body.is_empty());
+    assert_eq!(server.join().unwrap()[0], "HEAD /file HTTP/1.1\r\n");
+}
+
+#[test]
+fn early_responses_echo_the_request_id() {
+    let svr = test_server();
+    let raw = svr.handle_for_test(
+        b"POST /upload HTTP/1.1\r\nX-Request-Id: abc-123\r\nContent-Length: ten\r\n\r\n",
+    );
+    let (head, _) = split_response(&raw);
+    assert!(head.starts_with("HTTP/1.1 400"), "{head}");
+    assert!(head.contains("X-Request-Id: abc-123"), "{head}");
+}
@@ -0,0 +1,234 @@
+//! Tests that drive a listening server over real sockets.
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rustful::error::ServerError;
+use rustful::middleware::Next;
+use rustful::rest_server::{HttpRequest, HttpResponse, RestServer};
+
+/// Runs `f` against `svr` while it listens on an ephemeral port, then shuts
+/// the server down and waits for it to stop.
+fn with_server<F: FnOnce(SocketAddr)>(svr: &RestServer, f: F) {
+    let handle = svr.shutdown_handle();
+    thread::scope(|scope| {
+        let listening = scope.spawn(|| svr.listen());
+        while svr.local_addr().is_none() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        f(svr.local_addr().unwrap());
+        handle.shutdown();
+        listening.join().unwrap().unwrap();
+    });
+}
+
+/// Sends `request` on a new connection and returns everything the server
+/// sends back until it closes the connection.
+fn exchange(addr: SocketAddr, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    return response;
+}
+
+fn test_server() -> RestServer {
+    let mut svr = RestServer::builder()
+        .name("test")
+        .bind("127.0.0.1")
+        .port(0)
+        .build()
+        .unwrap();
+    svr.set_access_logger(|_| {});
+    return svr;
+}
+
+#[test]
+fn serves_requests_in_parallel() {
+    const CLIENTS: usize = 4;
+    const SLEEP: Duration = Duration::from_millis(300);
+
+    let mut svr = test_server();
+    svr.set_workers(CLIENTS);
+    svr.register_path("/slow", |_req| {
+        thread::sleep(SLEEP);
+        return Ok("done");
+    })
+    .unwrap();
+
+    with_server(&svr, |addr| {
+        let start = Instant::now();
+        let clients: Vec<_> = (0..CLIENTS)
+            .map(|_| {
+                thread::spawn(move || {
+                    exchange(addr, b"GET /slow HTTP/1.1\r\nConnection: close\r\n\r\n")
+                })
+            })
+            .collect();
+        for client in clients {
+            let response = client.join().unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+            assert!(response.ends_with("done"), "{response}");
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= SLEEP, "{elapsed:?}");
+        assert!(
+            elapsed < SLEEP * 2,
+            "requests ran one after another: {elapsed:?}"
+        );
+    });
+}
+
+#[test]
+fn times_out_a_stalled_request() {
+    let mut svr = test_server();
+    svr.set_read_timeout(Some(Duration::from_millis(200)));
+    svr.register_path("/ping", |_req| Ok("pong")).unwrap();
+
+    with_server(&svr, |addr| {
+        for partial in [&b"GET /pi"[..], b"GET /ping HTTP/1.1\r\nHost: a"] {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream.write_all(partial).unwrap();
+            thread::sleep(Duration::from_millis(400));
+            let mut response = String::new();
+            // Reading to the end only returns once the server has closed,
+            // and fails if it has not within the client's timeout
+            stream.read_to_string(&mut response).unwrap();
+            assert!(
+                response.starts_with("HTTP/1.1 408 Request Timeout"),
+                "{response}"
+            );
+            assert!(response.contains("Connection: close"), "{response}");
+        }
+    });
+}
+
+#[test]
+fn rejects_connections_over_the_limit() {
+    let mut svr = test_server();
+    svr.set_max_connections(Some(2));
+    svr.register_path("/ping", |_req| Ok("pong")).unwrap();
+
+    with_server(&svr, |addr| {
+        let held: Vec<TcpStream> = (0..2).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        // Give the accept loop time to take both connections
+        thread::sleep(Duration::from_millis(200));
+
+        let mut extra = TcpStream::connect(addr).unwrap();
+        extra
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut response = String::new();
+        // Ends only once the server has closed the connection
+        extra.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 503 Service Unavailable"),
+            "{response}"
+        );
+
+        // Closing the held connections frees their slots
+        drop(held);
+        thread::sleep(Duration::from_millis(200));
+        let response = exchange(addr, b"GET /ping HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    });
+}
+
+#[test]
+fn shuts_down_with_idle_keep_alive_connections() {
+    let mut svr = test_server();
+    svr.set_keep_alive_timeout(None);
+    svr.register_path("/", |_req| Ok("hi")).unwrap();
+
+    let handle = svr.shutdown_handle();
+    thread::scope(|scope| {
+        let listening = scope.spawn(|| svr.listen());
+        while svr.local_addr().is_none() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let mut stream = TcpStream::connect(svr.local_addr().unwrap()).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = [0; 1024];
+        let n = stream.read(&mut response).unwrap();
+        assert!(response[..n].starts_with(b"HTTP/1.1 200 OK"));
+
+        // The connection is idle and has no keep-alive timeout
+        let start = Instant::now();
+        handle.shutdown();
+        listening.join().unwrap().unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+        assert_eq!(stream.read(&mut response).unwrap(), 0);
+    });
+}
+
+#[test]
+fn retries_binding_a_port_in_use() {
+    let holder = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = holder.local_addr().unwrap().port();
+    let mut svr = RestServer::builder()
+        .name("test")
+        .bind("127.0.0.1")
+        .port(port)
+        .build()
+        .unwrap();
+    svr.set_access_logger(|_| {});
+
+    // Every attempt fails while the port is held
+    svr.bind_with_retry(3, Duration::from_millis(20));
+    let start = Instant::now();
+    let result = svr.listen();
+    assert!(
+        matches!(result, Err(ServerError::BindFailed { .. })),
+        "{result:?}"
+    );
+    assert!(start.elapsed() >= Duration::from_millis(40));
+
+    // A later attempt succeeds once the port is released
+    svr.bind_with_retry(50, Duration::from_millis(20));
+    let handle = svr.shutdown_handle();
+    thread::scope(|scope| {
+        let listening = scope.spawn(|| svr.listen());
+        thread::sleep(Duration::from_millis(100));
+        drop(holder);
+        while svr.local_addr().is_none() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        handle.shutdown();
+        listening.join().unwrap().unwrap();
+    });
+}
+
+/// Panics for `/boom` before any handler runs.
+fn panic_on_boom(req: HttpRequest, next: Next) -> Result<HttpResponse, ServerError> {
+    if req.path() == "/boom" {
+        panic!("middleware failed");
+    }
+    return next(req);
+}
+
+#[test]
+fn survives_a_panicking_middleware() {
+    let mut svr = test_server();
+    svr.set_workers(1);
+    svr.use_middleware(panic_on_boom);
+    svr.register_path("/ok", |_req| Ok("still here")).unwrap();
+
+    with_server(&svr, |addr| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /boom HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        assert!(response.is_empty(), "{response:?}");
+
+        // The only worker is still there for the next connection
+        let response = exchange(addr, b"GET /ok HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("still here"), "{response}");
+    });
+}